@@ -0,0 +1,65 @@
+# takes a derivation or an attribute set of packages, returns a list of `{ attr, drvPath, outputs, position }`.
+# nested attribute sets are only entered if they set `recurseForDerivations = true`,
+# broken packages, packages unavailable on this platform and packages failing to evaluate are skipped.
+value:
+let
+  isDerivation = v: builtins.isAttrs v && (v.type or null) == "derivation";
+  usable =
+    drv:
+    let
+      meta = drv.meta or { };
+    in
+    !(meta.broken or false) && (meta.available or true);
+  # `meta.position` is `file:line` of the package expression, if known
+  position =
+    v:
+    let
+      res = builtins.tryEval (v.meta.position or null);
+    in
+    if res.success then res.value else null;
+  # output name to store path, empty if any of them fails to evaluate
+  outputs =
+    v:
+    let
+      paths = builtins.listToAttrs (
+        map (o: {
+          name = o;
+          value = v.${o}.outPath;
+        }) (v.outputs or [ "out" ])
+      );
+      res = builtins.tryEval (builtins.deepSeq paths paths);
+    in
+    if res.success then res.value else { };
+  drvEntry =
+    path: v:
+    let
+      res = builtins.tryEval (if path == "" || usable v then v.drvPath else null);
+    in
+    if res.success && res.value != null then
+      [
+        {
+          attr = path;
+          drvPath = res.value;
+          outputs = outputs v;
+          position = position v;
+        }
+      ]
+    else
+      [ ];
+  go =
+    path: v:
+    let
+      res = builtins.tryEval (
+        if isDerivation v then
+          drvEntry path v
+        else if builtins.isAttrs v && (path == "" || (v.recurseForDerivations or false)) then
+          builtins.concatLists (
+            builtins.attrValues (builtins.mapAttrs (n: go (if path == "" then n else "${path}.${n}")) v)
+          )
+        else
+          [ ]
+      );
+    in
+    if res.success then res.value else [ ];
+in
+go "" value
@@ -0,0 +1,269 @@
+use log::debug;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use crate::{
+    derivation::{drv_name, parse_drv_name, Derivation},
+    report::Advisory,
+};
+
+/// An entry of `vulnix --json` output.
+#[derive(Deserialize, Debug)]
+struct VulnixEntry {
+    pname: String,
+    version: String,
+    affected_by: Vec<String>,
+    #[serde(default)]
+    cvssv3_basescore: HashMap<String, f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsvPackage {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsvAffected {
+    package: OsvPackage,
+    /// affected versions, entries with only ranges are skipped
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+/// An OSV vulnerability record, only explicit version lists are supported.
+#[derive(Deserialize, Debug)]
+struct OsvEntry {
+    id: String,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Entry {
+    Vulnix(VulnixEntry),
+    Osv(OsvEntry),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AdvisoryFile {
+    List(Vec<Entry>),
+    /// `osv-scanner`/`querybatch` style
+    Osv {
+        vulns: Vec<OsvEntry>,
+    },
+}
+
+#[derive(Debug)]
+struct Vulnerable {
+    id: String,
+    version: String,
+    score: Option<f64>,
+}
+
+/// Known vulnerabilities by package name, read from a local file.
+#[derive(Debug, Default)]
+pub struct Advisories {
+    by_pname: HashMap<String, Vec<Vulnerable>>,
+}
+
+impl Advisories {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let file: AdvisoryFile = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let advisories = Self::from_file(file);
+        debug!(
+            "read advisories for {} packages from {}",
+            advisories.by_pname.len(),
+            path.display()
+        );
+        Ok(advisories)
+    }
+
+    fn from_file(file: AdvisoryFile) -> Self {
+        let entries = match file {
+            AdvisoryFile::List(entries) => entries,
+            AdvisoryFile::Osv { vulns } => vulns.into_iter().map(Entry::Osv).collect(),
+        };
+
+        let mut advisories = Advisories::default();
+        for entry in entries {
+            match entry {
+                Entry::Vulnix(v) => {
+                    let known = advisories.by_pname.entry(v.pname).or_default();
+                    for id in v.affected_by {
+                        known.push(Vulnerable {
+                            score: v.cvssv3_basescore.get(&id).copied(),
+                            id,
+                            version: v.version.clone(),
+                        });
+                    }
+                }
+                Entry::Osv(osv) => {
+                    for affected in osv.affected {
+                        if affected.versions.is_empty() {
+                            debug!(
+                                "skipping {} of {}, it lists no explicit versions",
+                                osv.id, affected.package.name
+                            );
+                            continue;
+                        }
+                        let known = advisories
+                            .by_pname
+                            .entry(affected.package.name)
+                            .or_default();
+                        for version in affected.versions {
+                            known.push(Vulnerable {
+                                id: osv.id.clone(),
+                                version,
+                                score: None,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        advisories
+    }
+
+    /// Advisories affecting a package, `version` may carry an output suffix like `-dev`.
+    pub fn matching(&self, pname: &str, version: &str) -> Vec<Advisory> {
+        let Some(known) = self.by_pname.get(pname) else {
+            return Vec::new();
+        };
+        known
+            .iter()
+            .filter(|v| {
+                version == v.version
+                    || version
+                        .strip_prefix(&v.version)
+                        .is_some_and(|rest| rest.starts_with('-'))
+            })
+            .map(|v| Advisory {
+                id: v.id.clone(),
+                package: format!("{}-{}", pname, version),
+                score: v.score,
+                unverified: false,
+            })
+            .collect()
+    }
+
+    /// Advisories removed from a closure together with `dep`, i.e. of the store paths only it brings in,
+    /// the outputs of `dep` included if nothing else keeps them. Each advisory is only listed once.
+    /// If the closure could not be measured, only the advisories of `dep` itself are known, as unverified.
+    pub fn for_dep(&self, dep: &Derivation, exclusive_paths: Option<&[String]>) -> Vec<Advisory> {
+        let mut found: BTreeMap<String, Advisory> = BTreeMap::new();
+        let Some(exclusive_paths) = exclusive_paths else {
+            let own = dep
+                .pname()
+                .zip(dep.version())
+                .map(|(pname, version)| self.matching(pname, version))
+                .unwrap_or_default();
+            for advisory in own {
+                found.entry(advisory.id.clone()).or_insert(Advisory {
+                    unverified: true,
+                    ..advisory
+                });
+            }
+            return found.into_values().collect();
+        };
+        for path in exclusive_paths {
+            let name = drv_name(path);
+            let (pname, version) = parse_drv_name(&name);
+            for advisory in self.matching(pname, version) {
+                found.entry(advisory.id.clone()).or_insert(advisory);
+            }
+        }
+        found.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advisories(json: &str) -> Advisories {
+        Advisories::from_file(serde_json::from_str(json).unwrap())
+    }
+
+    fn ids(advisories: Vec<Advisory>) -> Vec<String> {
+        advisories.into_iter().map(|a| a.id).collect()
+    }
+
+    #[test]
+    fn osv_explicit_versions() {
+        let advisories = advisories(
+            r#"{ "vulns": [ { "id": "CVE-2024-1", "affected": [
+                { "package": { "name": "zlib" }, "versions": [ "1.3", "1.3.1" ] }
+            ] } ] }"#,
+        );
+        assert_eq!(ids(advisories.matching("zlib", "1.3.1")), ["CVE-2024-1"]);
+        assert_eq!(ids(advisories.matching("zlib", "1.3-dev")), ["CVE-2024-1"]);
+        assert!(advisories.matching("zlib", "1.3.2").is_empty());
+        assert!(advisories.matching("zlib", "1.3.10").is_empty());
+    }
+
+    #[test]
+    fn osv_ranges_are_skipped() {
+        let advisories = advisories(
+            r#"[ { "id": "CVE-2024-2", "affected": [
+                { "package": { "name": "zlib" },
+                  "ranges": [ { "type": "SEMVER", "events": [ { "introduced": "0" }, { "fixed": "1.3.1" } ] } ] }
+            ] } ]"#,
+        );
+        assert!(advisories.matching("zlib", "1.3").is_empty());
+        assert!(advisories.matching("zlib", "1.4").is_empty());
+    }
+
+    #[test]
+    fn vulnix_entries() {
+        let advisories = advisories(
+            r#"[ { "pname": "zlib", "version": "1.3", "affected_by": [ "CVE-2024-3" ],
+                   "cvssv3_basescore": { "CVE-2024-3": 9.8 } } ]"#,
+        );
+        let found = advisories.matching("zlib", "1.3");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].score, Some(9.8));
+        assert_eq!(found[0].package, "zlib-1.3");
+    }
+
+    const ZLIB_OSV: &str = r#"[ { "id": "CVE-2024-1", "affected": [
+        { "package": { "name": "zlib" }, "versions": [ "1.3" ] }
+    ] } ]"#;
+
+    fn zlib() -> Derivation {
+        serde_json::from_value(serde_json::json!({
+            "env": { "pname": "zlib", "version": "1.3" },
+            "outputs": { "out": { "path": "/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-zlib-1.3" } },
+            "inputDrvs": {},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn kept_outputs_remove_nothing() {
+        // e.g. a used libpng still pulls in zlib
+        let found = advisories(ZLIB_OSV).for_dep(&zlib(), Some(&[]));
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn exclusive_outputs_remove_their_advisories() {
+        let paths = ["/nix/store/zzzzzzzzzzzzzzzzzzzzzzzzzzzzzzzz-zlib-1.3".to_owned()];
+        let found = advisories(ZLIB_OSV).for_dep(&zlib(), Some(&paths));
+        assert_eq!(ids(found.clone()), ["CVE-2024-1"]);
+        assert!(!found[0].unverified);
+    }
+
+    #[test]
+    fn unmeasured_closures_are_unverified() {
+        let found = advisories(ZLIB_OSV).for_dep(&zlib(), None);
+        assert_eq!(ids(found.clone()), ["CVE-2024-1"]);
+        assert!(found[0].unverified);
+    }
+}
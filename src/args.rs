@@ -1,13 +1,27 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
-use clap::{ArgAction, Parser};
-use clap_stdin::MaybeStdin;
+use clap::{ArgAction, ArgGroup, Parser, Subcommand};
+use clap_stdin::{FileOrStdin, MaybeStdin};
+use regex::Regex;
+use std::path::PathBuf;
+
+use crate::{output::Format, runstate::Shard, scope::ClosureKind};
 
 #[derive(Debug, Parser)]
-#[clap(name="nix-check-deps", version=env!("CARGO_PKG_VERSION"),about=env!("CARGO_PKG_DESCRIPTION"), author=env!("CARGO_PKG_AUTHORS"))]
+#[clap(name="nix-check-deps", version=env!("CARGO_PKG_VERSION"),about=env!("CARGO_PKG_DESCRIPTION"), author=env!("CARGO_PKG_AUTHORS"), subcommand_negates_reqs = true)]
+// modes that walk a closure for further roots, see `Scope`
+#[clap(group(ArgGroup::new("walk").args(["tree", "system"]).multiple(true)))]
 pub struct Cli {
-    /// package to evaluate
-    pub attr: MaybeStdin<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// package to evaluate, `.drv` file or output store path (e.g. `./result`), `-` reads a list of targets from stdin
+    #[clap(required_unless_present = "targets")]
+    pub attr: Option<MaybeStdin<String>>,
+
+    /// file listing packages to scan, one attr/drv path or JSON object with `attr`/`drvPath` per line, `-` for stdin
+    #[arg(long)]
+    pub targets: Option<FileOrStdin>,
 
     /// Don't scan for c header files in use
     #[clap(long = "no-check-headers", action = ArgAction::SetFalse)]
@@ -33,18 +47,98 @@ pub struct Cli {
     #[arg(long, default_value_t = false)]
     pub skip_dep_usage_check: bool,
 
-    /// output json
+    /// compute the closure size each unused dependency adds to its root and sort findings by it
+    #[arg(long, default_value_t = false)]
+    pub closure_size: bool,
+
+    /// local vulnix (`vulnix --json`) or OSV JSON file, unused dependencies removing known vulnerabilities are ranked first
+    #[arg(long)]
+    pub advisories: Option<PathBuf>,
+
+    /// JSON lines index of a package set from `nix-check-deps index` or `nix-eval-jobs`, to show attribute paths of dependencies
+    #[arg(long)]
+    pub attr_index: Option<PathBuf>,
+
+    /// print summary statistics over all roots instead of the individual findings
     #[arg(long, default_value_t = false)]
+    pub stats: bool,
+
+    /// don't report findings already recorded in this baseline file
+    #[arg(long)]
+    pub baseline: Option<PathBuf>,
+
+    /// write all current findings to the `--baseline` file instead of suppressing them
+    #[arg(long, default_value_t = false, requires = "baseline")]
+    pub update_baseline: bool,
+
+    /// output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// same as `--format json`
+    #[arg(long, default_value_t = false, conflicts_with = "format")]
     pub json: bool,
 
     /// scan all transient dependents of a derivation
     #[arg(long, default_value_t = false)]
     pub tree: bool,
 
+    /// only scan packages of an attribute set whose attribute path matches this regex
+    #[arg(long)]
+    pub include: Option<Regex>,
+
+    /// skip packages of an attribute set whose attribute path matches this regex
+    #[arg(long)]
+    pub exclude: Option<Regex>,
+
+    /// scan every package in the runtime closure of a NixOS or home-manager system and rank findings by closure size saved
+    #[arg(long, default_value_t = false)]
+    pub system: bool,
+
+    /// closure to walk in `--tree` mode
+    #[arg(long, value_enum, default_value_t = ClosureKind::Build, requires = "tree")]
+    pub closure: ClosureKind,
+
+    /// only walk the build closure this many `inputDrvs` levels deep in `--tree` mode, not with `--closure runtime`
+    #[arg(long, requires = "tree", conflicts_with = "system")]
+    pub max_depth: Option<usize>,
+
+    /// in `--tree` or `--system` mode, only scan derivations that have `buildInputs`
+    #[arg(long, default_value_t = false, requires = "walk")]
+    pub only_with_build_inputs: bool,
+
+    /// in `--tree` or `--system` mode, don't scan fixed-output derivations like fetchers
+    #[arg(long, default_value_t = false, requires = "walk")]
+    pub exclude_fixed_output: bool,
+
+    /// in `--tree` or `--system` mode, only scan derivations whose pname matches this regex
+    #[arg(long, requires = "walk")]
+    pub pname_pattern: Option<Regex>,
+
+    /// additional configuration file, read after the user and project configuration
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// don't load the shipped default configuration
+    #[arg(long, default_value_t = false)]
+    pub no_default_config: bool,
+
     /// drv names to skip
     #[arg(long, default_value_t = String::from(""))]
     pub skip: String,
 
+    /// record every completed root in this file, so an interrupted run can be resumed
+    #[arg(long)]
+    pub state: Option<PathBuf>,
+
+    /// continue the run recorded in the `--state` file, skipping roots already completed
+    #[arg(long, default_value_t = false, requires = "state")]
+    pub resume: bool,
+
+    /// only scan a deterministic part of all roots, e.g. `3/16`
+    #[arg(long)]
+    pub shard: Option<Shard>,
+
     /// number of packages to check at once [broken]
     #[arg(long, short, default_value_t = 1)]
     pub jobs: usize,
@@ -53,3 +147,40 @@ pub struct Cli {
     #[arg(last(false), allow_hyphen_values = true, value_delimiter(' '))]
     pub nix_flags: Option<Vec<String>>,
 }
+
+impl Cli {
+    pub fn format(&self) -> Format {
+        if self.json {
+            Format::Json
+        } else {
+            self.format
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// merge the findings of the `--state` files of several (sharded) runs into one report
+    Merge {
+        #[arg(required = true)]
+        state_files: Vec<PathBuf>,
+    },
+    /// remove the unused dependencies recorded in `--state` files from their nix files and print unified diffs
+    Fix {
+        /// only print the diffs, this is the default
+        #[arg(long, default_value_t = false, conflicts_with = "write")]
+        dry_run: bool,
+        /// also edit the nix files in place
+        #[arg(long, default_value_t = false)]
+        write: bool,
+        /// local nixpkgs checkout to edit instead of the nix files evaluated from the nix store
+        #[arg(long)]
+        nixpkgs: Option<PathBuf>,
+        #[arg(required = true)]
+        state_files: Vec<PathBuf>,
+    },
+    /// print a JSON lines index of the attribute paths, drv paths and outputs of every package in a package set, for `--attr-index`
+    Index { installable: String },
+    /// print the JSON Schema of the `--json` report
+    Schema,
+}
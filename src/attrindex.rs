@@ -0,0 +1,135 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    derivation,
+    report::{RootReport, SourcePosition},
+};
+
+/// A package of a walked package set, one JSON line of the index.
+/// `nix-eval-jobs` output lines are entries as well.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEntry {
+    /// attribute path relative to the package set, e.g. `xorg.libXv`
+    pub attr: String,
+    pub drv_path: String,
+    /// output name to store path, `nix-eval-jobs` sets unknown paths to `null`
+    #[serde(default)]
+    pub outputs: BTreeMap<String, Option<String>>,
+    /// `meta.position` of the package, `file:line`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+}
+
+/// Attribute paths of drv and output paths, read from an index built by `nix-check-deps index`.
+#[derive(Debug, Default)]
+pub struct AttrIndex {
+    entries: Vec<IndexEntry>,
+    /// drv or output path to indices of `entries`
+    by_path: HashMap<String, Vec<usize>>,
+}
+
+/// Walks a package set like scanning it does, see `recurse-attrs.nix`.
+pub fn build(installable: &str) -> Option<Vec<IndexEntry>> {
+    let evaluated = derivation::eval_attr_to_drv_paths(installable)?;
+    Some(
+        evaluated
+            .into_iter()
+            .map(|e| IndexEntry {
+                attr: if e.attr.is_empty() {
+                    installable.to_owned()
+                } else {
+                    e.attr
+                },
+                drv_path: e.drv_path,
+                outputs: e.outputs.into_iter().map(|(n, p)| (n, Some(p))).collect(),
+                position: e.position,
+            })
+            .collect(),
+    )
+}
+
+impl AttrIndex {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut index = AttrIndex::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // e.g. `nix-eval-jobs` lines of attributes that failed to evaluate
+            match serde_json::from_str::<IndexEntry>(&line) {
+                Ok(entry) => index.insert(entry),
+                Err(e) => debug!("skipping index line {}: {}", line, e),
+            }
+        }
+        debug!(
+            "read {} attributes from {}",
+            index.entries.len(),
+            path.display()
+        );
+        Ok(index)
+    }
+
+    fn insert(&mut self, entry: IndexEntry) {
+        let i = self.entries.len();
+        let paths = std::iter::once(&entry.drv_path).chain(entry.outputs.values().flatten());
+        for path in paths {
+            self.by_path.entry(path.clone()).or_default().push(i);
+        }
+        self.entries.push(entry);
+    }
+
+    fn entries<'a>(&'a self, paths: impl IntoIterator<Item = &'a String>) -> Vec<&'a IndexEntry> {
+        let mut found: Vec<usize> = paths
+            .into_iter()
+            .flat_map(|p| self.by_path.get(p).into_iter().flatten().copied())
+            .collect();
+        found.sort();
+        found.dedup();
+        found.into_iter().map(|i| &self.entries[i]).collect()
+    }
+
+    /// Attribute paths of a derivation, matched by its drv path or any of its outputs, shortest first.
+    pub fn attrs<'a>(
+        &'a self,
+        drv_path: &'a String,
+        outputs: impl IntoIterator<Item = &'a String>,
+    ) -> Vec<String> {
+        let mut attrs: Vec<String> = self
+            .entries(std::iter::once(drv_path).chain(outputs))
+            .into_iter()
+            .map(|e| e.attr.clone())
+            .collect();
+        attrs.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        attrs.dedup();
+        attrs
+    }
+
+    /// Adds the attribute paths of the dependencies of a report, and of its root if it was not evaluated from an attribute.
+    pub fn annotate(&self, report: &mut RootReport) {
+        if report.attr.is_none() {
+            let entries = self.entries([&report.root]);
+            report.attr = self.attrs(&report.root, []).into_iter().next();
+            if report.position.is_none() {
+                report.position = entries
+                    .iter()
+                    .flat_map(|e| e.position.as_deref())
+                    .find_map(SourcePosition::from_meta_position);
+            }
+        }
+        for dep in report.unused.iter_mut() {
+            dep.attrs = self.attrs(&dep.drv_path, dep.outputs.values());
+        }
+        for dep in report.used.iter_mut() {
+            dep.attrs = self.attrs(&dep.drv_path, []);
+        }
+    }
+}
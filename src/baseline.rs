@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    derivation::drv_name,
+    report::{RootReport, UnusedDep},
+};
+
+const BASELINE_VERSION: u32 = 1;
+
+/// A known finding, identified by names instead of drv paths so it survives rebuilds and version bumps.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BaselineEntry {
+    pub root: String,
+    pub dep: String,
+}
+
+/// Findings that were already known and are not reported again.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Baseline {
+    pub version: u32,
+    pub findings: BTreeSet<BaselineEntry>,
+}
+
+/// Identifies a finding by the names of root and dependency.
+pub fn entry(report: &RootReport, dep: &UnusedDep) -> BaselineEntry {
+    BaselineEntry {
+        root: report.name(),
+        dep: dep.pname.clone().unwrap_or_else(|| drv_name(&dep.drv_path)),
+    }
+}
+
+impl Baseline {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let baseline: Baseline = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if baseline.version != BASELINE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported baseline version {}", baseline.version),
+            ));
+        }
+        Ok(baseline)
+    }
+
+    pub fn from_reports(reports: &[RootReport]) -> Self {
+        Baseline {
+            version: BASELINE_VERSION,
+            findings: reports
+                .iter()
+                .flat_map(|r| r.unused.iter().map(move |d| entry(r, d)))
+                .collect(),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Removes all findings already in the baseline from the reports.
+    pub fn suppress(&self, reports: &mut [RootReport]) {
+        for report in reports.iter_mut() {
+            let known: Vec<bool> = report
+                .unused
+                .iter()
+                .map(|d| self.findings.contains(&entry(report, d)))
+                .collect();
+            let mut known = known.into_iter();
+            report.unused.retain(|_| !known.next().unwrap_or(false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report(root: &str, pname: &str, unused: &[(&str, &str)]) -> RootReport {
+        serde_json::from_value(json!({
+            "root": root,
+            "pname": pname,
+            "unused": unused
+                .iter()
+                .map(|(drv_path, pname)| json!({ "drv_path": drv_path, "pname": pname }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn entries_use_names() {
+        let mpv = report(
+            "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-mpv-0.38.drv",
+            "mpv",
+            &[],
+        );
+        let dep = |json| serde_json::from_value::<UnusedDep>(json).unwrap();
+        let dbus = dep(
+            json!({ "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-dbus-1.14.drv", "pname": "dbus" }),
+        );
+        let unnamed =
+            dep(json!({ "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-source.drv" }));
+        assert_eq!(
+            entry(&mpv, &dbus),
+            BaselineEntry {
+                root: "mpv".to_owned(),
+                dep: "dbus".to_owned(),
+            }
+        );
+        assert_eq!(entry(&mpv, &unnamed).dep, "source");
+
+        let mut attr = mpv.clone();
+        attr.attr = Some("nixpkgs#mpv-unwrapped".to_owned());
+        assert_eq!(entry(&attr, &dbus).root, "nixpkgs#mpv-unwrapped");
+    }
+
+    #[test]
+    fn suppress_across_version_bumps() {
+        let baseline = Baseline::from_reports(&[report(
+            "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-mpv-0.38.drv",
+            "mpv",
+            &[(
+                "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-dbus-1.14.drv",
+                "dbus",
+            )],
+        )]);
+        let mut reports = [report(
+            "/nix/store/cccccccccccccccccccccccccccccccc-mpv-0.39.drv",
+            "mpv",
+            &[
+                (
+                    "/nix/store/dddddddddddddddddddddddddddddddd-dbus-1.16.drv",
+                    "dbus",
+                ),
+                (
+                    "/nix/store/eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee-libva-2.22.drv",
+                    "libva",
+                ),
+            ],
+        )];
+        baseline.suppress(&mut reports);
+        let unused: Vec<_> = reports[0]
+            .unused
+            .iter()
+            .map(|d| d.pname.as_deref())
+            .collect();
+        assert_eq!(unused, [Some("libva")]);
+    }
+}
@@ -0,0 +1,115 @@
+use log::{debug, error};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::get_nix_flags;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PathInfo {
+    #[serde(default)]
+    path: String,
+    nar_size: u64,
+    #[serde(default)]
+    references: Vec<String>,
+}
+
+// `nix path-info --json` returns a list up to nix 2.18 and an object keyed by store path after
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PathInfos {
+    List(Vec<PathInfo>),
+    Map(HashMap<String, Option<PathInfo>>),
+}
+
+/// Sizes and references of store paths, queried lazily from the local store and kept for the whole run.
+#[derive(Default)]
+pub struct ClosureIndex {
+    infos: HashMap<String, PathInfo>,
+}
+
+impl ClosureIndex {
+    fn load(&mut self, paths: &[String]) {
+        let missing: Vec<&String> = paths
+            .iter()
+            .filter(|p| !self.infos.contains_key(*p) && Path::new(p).exists())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let output = Command::new("nix")
+            .arg("path-info")
+            .arg("--json")
+            .arg("--recursive")
+            .args(missing)
+            .args(get_nix_flags())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .output();
+        let infos = match output.map(|o| serde_json::from_slice::<PathInfos>(&o.stdout)) {
+            Ok(Ok(infos)) => infos,
+            Ok(Err(e)) => {
+                error!("can not parse nix path-info output: {}", e);
+                return;
+            }
+            Err(e) => {
+                error!("can not run nix path-info: {}", e);
+                return;
+            }
+        };
+        match infos {
+            PathInfos::List(list) => {
+                self.infos
+                    .extend(list.into_iter().map(|i| (i.path.clone(), i)));
+            }
+            PathInfos::Map(map) => {
+                self.infos.extend(
+                    map.into_iter()
+                        .flat_map(|(path, info)| info.map(|i| (path, i))),
+                );
+            }
+        }
+    }
+
+    /// All store paths reachable from `paths`, `None` if any of them is not in the local store.
+    pub fn closure(&mut self, paths: &[String]) -> Option<HashSet<String>> {
+        self.load(paths);
+        let mut closure: HashSet<String> = HashSet::new();
+        let mut todo: Vec<&String> = paths.iter().collect();
+        while let Some(path) = todo.pop() {
+            let Some(info) = self.infos.get(path) else {
+                debug!("{} is not in the local store", path);
+                return None;
+            };
+            if closure.insert(path.clone()) {
+                todo.extend(info.references.iter());
+            }
+        }
+        Some(closure)
+    }
+
+    /// Everything in the closure of `removed` that is not also in the closure of `kept`,
+    /// i.e. what disappears if `removed` is no longer needed. `None` if either closure is not in the local store.
+    pub fn exclusive_paths(&mut self, removed: &[String], kept: &[String]) -> Option<Vec<String>> {
+        let kept = self.closure(kept)?;
+        Some(
+            self.closure(removed)?
+                .into_iter()
+                .filter(|p| !kept.contains(p))
+                .collect(),
+        )
+    }
+
+    /// Bytes of [`Self::exclusive_paths`].
+    pub fn size(&self, paths: &[String]) -> u64 {
+        paths
+            .iter()
+            .flat_map(|p| self.infos.get(p))
+            .map(|i| i.nar_size)
+            .sum()
+    }
+}
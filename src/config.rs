@@ -0,0 +1,276 @@
+use log::debug;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::derivation::Derivation;
+
+/// Name of the project-local configuration file, looked up in the working directory.
+const PROJECT_CONFIG: &str = "nix-check-deps.toml";
+
+/// Shipped defaults, loaded before any other configuration.
+const DEFAULT_CONFIG: &str = include_str!("../default-config.toml");
+
+/// A regex read from a configuration file.
+#[derive(Debug, Clone)]
+pub struct Pattern(pub Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Pattern {
+    fn is_match(&self, s: &str) -> bool {
+        self.0.is_match(s)
+    }
+}
+
+/// A dependency that is never reported as unused.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Allow {
+    /// matched against the drv path of the dependency
+    pub pattern: Pattern,
+    pub reason: String,
+}
+
+/// Dependencies that are not reported as unused for specific roots only.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RootOverride {
+    /// matched against drv path, pname and attribute of the root
+    pub root: Pattern,
+    /// matched against the drv path of the dependency
+    pub ignore: Vec<Pattern>,
+    pub reason: String,
+}
+
+/// Scanners to run, `None` keeps the setting of earlier configuration files.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ScannerConfig {
+    pub headers: Option<bool>,
+    pub pyproject: Option<bool>,
+    pub shebangs: Option<bool>,
+    pub shared_objects: Option<bool>,
+}
+
+/// Roots that are not scanned at all.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SkipConfig {
+    #[serde(default)]
+    pub pname: Vec<Pattern>,
+    #[serde(default)]
+    pub attr: Vec<Pattern>,
+    #[serde(default)]
+    pub drv: Vec<Pattern>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub allow: Vec<Allow>,
+    #[serde(default, rename = "override")]
+    pub overrides: Vec<RootOverride>,
+    #[serde(default)]
+    pub scanners: ScannerConfig,
+    #[serde(default)]
+    pub skip: SkipConfig,
+}
+
+impl Config {
+    fn parse(contents: &str, origin: &str) -> io::Result<Self> {
+        toml::from_str(contents).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid configuration {}: {}", origin, e),
+            )
+        })
+    }
+
+    fn read(path: &Path) -> io::Result<Self> {
+        debug!("reading configuration {}", path.display());
+        Self::parse(&fs::read_to_string(path)?, &path.display().to_string())
+    }
+
+    /// Later configuration extends the lists of earlier configuration and overrides its scanner settings.
+    fn merge(&mut self, other: Config) {
+        self.allow.extend(other.allow);
+        self.overrides.extend(other.overrides);
+        self.skip.pname.extend(other.skip.pname);
+        self.skip.attr.extend(other.skip.attr);
+        self.skip.drv.extend(other.skip.drv);
+        let scanners = &mut self.scanners;
+        scanners.headers = other.scanners.headers.or(scanners.headers);
+        scanners.pyproject = other.scanners.pyproject.or(scanners.pyproject);
+        scanners.shebangs = other.scanners.shebangs.or(scanners.shebangs);
+        scanners.shared_objects = other.scanners.shared_objects.or(scanners.shared_objects);
+    }
+
+    /// Loads the shipped defaults, the user configuration, the project configuration and `extra`, in that order.
+    pub fn load(extra: Option<&Path>, defaults: bool) -> io::Result<Self> {
+        let mut config = if defaults {
+            Self::parse(DEFAULT_CONFIG, "default-config.toml")?
+        } else {
+            Config::default()
+        };
+
+        let candidates = [user_config_path(), Some(PathBuf::from(PROJECT_CONFIG))];
+        for path in candidates.into_iter().flatten() {
+            if path.exists() {
+                config.merge(Self::read(&path)?);
+            }
+        }
+        if let Some(path) = extra {
+            config.merge(Self::read(path)?);
+        }
+        Ok(config)
+    }
+
+    /// Why `dep` may be unused in `root`, if it is allowed to be.
+    pub fn allowed_reason(&self, root: &Derivation, attr: Option<&str>, dep: &str) -> Option<&str> {
+        if let Some(allow) = self.allow.iter().find(|a| a.pattern.is_match(dep)) {
+            return Some(&allow.reason);
+        }
+        self.overrides
+            .iter()
+            .filter(|o| root_matches(&o.root, root, attr))
+            .find(|o| o.ignore.iter().any(|p| p.is_match(dep)))
+            .map(|o| o.reason.as_str())
+    }
+
+    pub fn is_skipped(&self, root: &Derivation, attr: Option<&str>) -> bool {
+        self.skip.drv.iter().any(|p| p.is_match(&root.drv_path))
+            || root
+                .pname()
+                .is_some_and(|pname| self.skip.pname.iter().any(|p| p.is_match(pname)))
+            || attr.is_some_and(|attr| self.skip.attr.iter().any(|p| p.is_match(attr)))
+    }
+}
+
+fn root_matches(pattern: &Pattern, root: &Derivation, attr: Option<&str>) -> bool {
+    pattern.is_match(&root.drv_path)
+        || root.pname().is_some_and(|p| pattern.is_match(p))
+        || attr.is_some_and(|a| pattern.is_match(a))
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(config_home.join("nix-check-deps").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(drv_path: &str, pname: &str) -> Derivation {
+        let mut drv: Derivation = serde_json::from_value(serde_json::json!({
+            "env": { "pname": pname },
+            "outputs": {},
+            "inputDrvs": {},
+        }))
+        .unwrap();
+        drv.drv_path = drv_path.to_owned();
+        drv
+    }
+
+    fn parse(contents: &str) -> Config {
+        Config::parse(contents, "test").unwrap()
+    }
+
+    #[test]
+    fn merge_extends_lists_and_overrides_scanners() {
+        let mut config = parse(
+            r#"
+            allow = [{ pattern = "-dbus-", reason = "loaded at runtime" }]
+            skip.pname = ["^glibc$"]
+            [scanners]
+            headers = false
+            shebangs = false
+            "#,
+        );
+        config.merge(parse(
+            r#"
+            allow = [{ pattern = "-gsettings-", reason = "schemas" }]
+            skip.attr = ["^xorg\\."]
+            [scanners]
+            headers = true
+            "#,
+        ));
+        assert_eq!(config.allow.len(), 2);
+        assert_eq!(config.skip.pname.len(), 1);
+        assert_eq!(config.skip.attr.len(), 1);
+        assert_eq!(config.scanners.headers, Some(true));
+        assert_eq!(config.scanners.shebangs, Some(false));
+        assert_eq!(config.scanners.pyproject, None);
+    }
+
+    #[test]
+    fn allow_and_per_root_overrides() {
+        let config = parse(
+            r#"
+            allow = [{ pattern = "-dbus-", reason = "loaded at runtime" }]
+            [[override]]
+            root = "^mpv$"
+            ignore = ["-libva-"]
+            reason = "dlopened by mpv"
+            "#,
+        );
+        let mpv = root(
+            "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-mpv-0.38.drv",
+            "mpv",
+        );
+        let vlc = root(
+            "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-vlc-3.0.drv",
+            "vlc",
+        );
+        let dbus = "/nix/store/cccccccccccccccccccccccccccccccc-dbus-1.14.drv";
+        let libva = "/nix/store/dddddddddddddddddddddddddddddddd-libva-2.22.drv";
+
+        assert_eq!(
+            config.allowed_reason(&vlc, None, dbus),
+            Some("loaded at runtime")
+        );
+        assert_eq!(
+            config.allowed_reason(&mpv, None, libva),
+            Some("dlopened by mpv")
+        );
+        assert_eq!(config.allowed_reason(&vlc, None, libva), None);
+        // the override also matches the attribute of the root
+        assert_eq!(
+            config.allowed_reason(&vlc, Some("mpv"), libva),
+            Some("dlopened by mpv")
+        );
+    }
+
+    #[test]
+    fn skipped_by_pname_attr_or_drv() {
+        let config = parse(
+            r#"
+            [skip]
+            pname = ["^glibc$"]
+            attr = ["^xorg\\."]
+            drv = ["-bootstrap-"]
+            "#,
+        );
+        let drv = |name: &str| format!("/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-{}.drv", name);
+
+        assert!(config.is_skipped(&root(&drv("glibc-2.40"), "glibc"), None));
+        assert!(!config.is_skipped(&root(&drv("glibc-locales-2.40"), "glibc-locales"), None));
+        assert!(config.is_skipped(&root(&drv("libX11-1.8"), "libX11"), Some("xorg.libX11")));
+        assert!(!config.is_skipped(&root(&drv("libX11-1.8"), "libX11"), Some("libX11")));
+        assert!(config.is_skipped(&root(&drv("bootstrap-tools"), "tools"), None));
+    }
+}
@@ -3,14 +3,14 @@ use flate2::read::GzDecoder;
 use ignore::Walk;
 use lddtree::DependencyAnalyzer;
 use log::{debug, error, warn};
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use pyproject_toml::PyProjectToml;
 use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     fs::{self, File},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 use tar::Archive;
@@ -26,13 +26,28 @@ use std::{
     collections::HashMap,
     io::{BufRead, BufReader},
     process::{Command, Stdio},
+    sync::Mutex,
 };
 
-use crate::get_nix_flags;
+use crate::{
+    get_nix_flags,
+    report::{FilterReason, FilteredDep},
+};
+
+/// `nix derivation show` output of every derivation read so far, shared between all scan roots
+static DRV_CACHE: Lazy<Mutex<HashMap<String, serde_json::Value>>> = Lazy::new(Default::default);
+
+/// derivations that were already built successfully in this run
+static BUILT_DRVS: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+/// drv paths that failed to build in [`build_all`] and why, so they are not built again
+static FAILED_DRVS: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
 
 #[derive(Deserialize, Hash, Eq, PartialEq, Debug, Clone)]
 struct DrvOutput {
     path: String,
+    /// only set for fixed-output derivations
+    #[serde(default)]
+    hash: Option<String>,
 }
 
 impl DrvOutput {
@@ -57,6 +72,8 @@ struct DrvEnv {
     pname: Option<String>,
     #[serde(default)]
     propagated_build_inputs: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
     src: Option<String>,
 }
 
@@ -81,6 +98,15 @@ impl DrvEnv {
     }
 }
 
+/// Why the source scanners can not read the source of a derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnreadableSrc {
+    /// the source does not build or can not be unpacked
+    Broken,
+    /// an archive format the scanners can't unpack, e.g. `.zip`, `.tar.zst` or a wheel
+    Unsupported,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Derivation {
@@ -99,28 +125,18 @@ pub struct Derivation {
 
 impl Derivation {
     pub fn read_drv(drv_path: &str) -> Option<Self> {
-        let drv_path = if drv_path.ends_with(".drv") {
-            &format!("{}^*", drv_path)
-        } else {
-            drv_path
+        let cached = DRV_CACHE.lock().unwrap().get(drv_path).cloned();
+        let (path, json) = match cached {
+            Some(json) => (drv_path.to_owned(), json),
+            None => show_drvs(&[drv_path])?.into_iter().last()?,
         };
-        let output = Command::new("nix")
-            .arg("derivation")
-            .arg("show")
-            .arg(drv_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()
-            .ok()?;
-        let drvs: HashMap<String, Derivation> = serde_json::from_reader(output.stdout?)
+        let mut drv: Derivation = serde_json::from_value(json)
             .inspect_err(|e| {
-                error!("Can not parse drv {}: {}", drv_path, e);
+                error!("Can not parse drv {}: {}", path, e);
             })
-            .ok()?; // FIXME ?
-        drvs.into_iter().last().map(|(path, mut drv)| {
-            drv.drv_path = path.to_owned();
-            drv
-        })
+            .ok()?;
+        drv.drv_path = path;
+        Some(drv)
     }
 
     fn get_input_drv_paths(&self) -> Vec<String> {
@@ -134,18 +150,27 @@ impl Derivation {
             .find(|i| i.get_out_paths().contains(src_drv_path))
     }
 
-    fn read_src_dir(&self) -> Option<PathBuf> {
+    /// The store path of the source, built if needed.
+    fn src_path(&self) -> Option<PathBuf> {
+        let src = self.env.src.as_ref()?;
+        // path literals like `src = ./.` are copied to the store by the evaluation, they have no deriver
+        if Path::new(src).exists() {
+            return Some(PathBuf::from(src));
+        }
         // TODO: maybe integrate with https://github.com/milahu/nix-build-debug or similar
-        let src = self.get_src_drv()?;
-        let build_results = src.build().as_ref().ok()?;
-
+        let build_results = self.get_src_drv()?.build().as_ref().ok()?;
         let src_archive_path = PathBuf::from(build_results.first()?);
-        if !src_archive_path.exists() {
-            return None;
-        }
+        src_archive_path.exists().then_some(src_archive_path)
+    }
+
+    fn read_src_dir(&self) -> Option<PathBuf> {
+        let src_archive_path = self.src_path()?;
         if src_archive_path.is_dir() {
             return Some(src_archive_path);
         }
+        if !is_supported_archive(&src_archive_path) {
+            return None;
+        }
 
         self.extracted_src_archive
             .get_or_init(|| try_extract_source_archive(src_archive_path))
@@ -153,7 +178,22 @@ impl Derivation {
             .map(|t| t.path().to_path_buf())
     }
 
-    fn get_inputs(&self) -> &Vec<Derivation> {
+    /// Why the source scanners can not read the source of the derivation, `None` if it can be read or there is none.
+    pub fn unreadable_src(&self) -> Option<UnreadableSrc> {
+        self.env.src.as_ref()?;
+        match self.src_path() {
+            None => Some(UnreadableSrc::Broken),
+            Some(path) if !path.is_dir() && !is_supported_archive(&path) => {
+                Some(UnreadableSrc::Unsupported)
+            }
+            Some(_) => self
+                .read_src_dir()
+                .is_none()
+                .then_some(UnreadableSrc::Broken),
+        }
+    }
+
+    pub fn get_inputs(&self) -> &Vec<Derivation> {
         self.parsed_input_drvs.get_or_init(|| {
             self.input_drvs
                 .keys()
@@ -162,6 +202,14 @@ impl Derivation {
         })
     }
 
+    /// Own outputs by name, without those of inputs with the same pname.
+    pub fn get_outputs(&self) -> BTreeMap<String, String> {
+        self.outputs
+            .iter()
+            .map(|(name, o)| (name.clone(), o.path()))
+            .collect()
+    }
+
     pub fn get_out_paths(&self) -> Vec<String> {
         let mut outputs: Vec<String> = self.outputs.values().map(DrvOutput::path).collect();
 
@@ -177,10 +225,12 @@ impl Derivation {
         outputs
     }
 
-    pub fn read_deps(&self) -> Vec<Derivation> {
+    /// Inputs listed in `buildInputs` that need to be checked, and all other inputs with the reason they are not.
+    pub fn read_deps(&self) -> (Vec<Derivation>, Vec<FilteredDep>) {
         let dev_inputs: Vec<String> = self.env.get_build_inputs();
 
         let mut dep_relations: Vec<Derivation> = Vec::new();
+        let mut filtered: Vec<FilteredDep> = Vec::new();
         let mut propagated: Vec<String> = Vec::new();
         let check_inputs = self.env.get_check_inputs();
 
@@ -194,21 +244,41 @@ impl Derivation {
 
             if outputs.iter().any(|o| dev_inputs.contains(o)) {
                 dep_relations.push(dep_drv);
+            } else {
+                filtered.push(FilteredDep::new(
+                    &dep_drv.drv_path,
+                    FilterReason::NotBuildInput,
+                ));
             }
             propagated.append(&mut propagated_drvs.clone());
         }
 
         dep_relations.retain(|dep_drv| {
-            !propagated
+            let is_propagated = propagated
                 .iter()
-                .any(|p| dep_drv.get_out_paths().contains(p))
+                .any(|p| dep_drv.get_out_paths().contains(p));
+            if is_propagated {
+                filtered.push(FilteredDep::new(
+                    &dep_drv.drv_path,
+                    FilterReason::Propagated,
+                ));
+            }
+            !is_propagated
         });
         dep_relations.retain(|dep_drv| {
-            !check_inputs
+            let is_check_input = check_inputs
                 .iter()
-                .any(|p| dep_drv.get_out_paths().contains(p))
+                .any(|p| dep_drv.get_out_paths().contains(p));
+            if is_check_input {
+                filtered.push(FilteredDep::new(
+                    &dep_drv.drv_path,
+                    FilterReason::CheckInput,
+                ));
+            }
+            !is_check_input
         });
-        dep_relations
+        filtered.sort_by(|a, b| a.drv_path.cmp(&b.drv_path));
+        (dep_relations, filtered)
     }
 
     pub fn matches_pname(&self, pname: &str) -> bool {
@@ -339,7 +409,7 @@ impl Derivation {
                             || ft.mime_type() == "application/x-sharedlib"
                     });
                 if is_so {
-                    shared_objects.extend(fs::canonicalize(e.path()).into_iter());
+                    shared_objects.extend(fs::canonicalize(e.path()));
                 }
             }
         }
@@ -366,6 +436,75 @@ impl Derivation {
             .collect()
     }
 
+    /// Like [`Derivation::get_requisites`], but only follows `inputDrvs` up to `max_depth` levels deep.
+    pub fn get_requisites_to_depth(&self, max_depth: usize) -> Vec<Derivation> {
+        let mut seen: HashSet<String> = HashSet::from([self.drv_path.clone()]);
+        let mut frontier: Vec<String> = vec![self.drv_path.clone()];
+        let mut requisites: Vec<String> = Vec::new();
+        for _ in 0..max_depth {
+            preload_drvs(&frontier);
+            let next: Vec<String> = frontier
+                .iter()
+                .flat_map(|p| Derivation::read_drv(p).into_iter())
+                .flat_map(|d| d.get_input_drv_paths())
+                .filter(|p| seen.insert(p.clone()))
+                .collect();
+            requisites.extend(next.iter().cloned());
+            frontier = next;
+        }
+        preload_drvs(&requisites);
+        requisites
+            .iter()
+            .flat_map(|p| Derivation::read_drv(p).into_iter())
+            .collect()
+    }
+
+    /// Derivations of everything in the runtime closure of this derivation's outputs.
+    /// Store paths without a known or present deriver are left out.
+    pub fn get_runtime_requisites(&self) -> Vec<Derivation> {
+        let Ok(outputs) = self.build() else {
+            return Vec::new();
+        };
+        let Ok(output) = Command::new("nix-store")
+            .arg("--query")
+            .arg("-R")
+            .args(outputs.iter().filter(|o| Path::new(o).exists()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .output()
+        else {
+            return Vec::new();
+        };
+        let paths: Vec<String> = output.stdout.lines().map_while(Result::ok).collect();
+        let mut seen: HashSet<String> = HashSet::new();
+        let derivers: Vec<String> = query_derivers(&paths)
+            .into_iter()
+            .flatten()
+            .filter(|d| Path::new(d).exists() && seen.insert(d.clone()))
+            .collect();
+        preload_drvs(&derivers);
+        derivers
+            .iter()
+            .flat_map(|p| Derivation::read_drv(p).into_iter())
+            .collect()
+    }
+
+    pub fn is_fixed_output(&self) -> bool {
+        self.outputs.values().any(|o| o.hash.is_some())
+    }
+
+    pub fn has_build_inputs(&self) -> bool {
+        !self.env.get_build_inputs().is_empty()
+    }
+
+    pub fn pname(&self) -> Option<&str> {
+        self.env.pname.as_deref()
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.env.version.as_deref()
+    }
+
     pub fn find_used_c_headers(&self) -> HashSet<String> {
         let src_dir = if let Some(src_dir) = self.read_src_dir() {
             src_dir
@@ -422,14 +561,26 @@ impl Derivation {
             } else {
                 build_path
             };
-            Command::new("nix")
-                .arg("build")
-                .arg(build_path)
-                .arg("--no-link")
-                .args(get_nix_flags())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::inherit())
-                .status()?;
+            if let Some(e) = FAILED_DRVS.lock().unwrap().get(&self.drv_path) {
+                return Err(std::io::Error::other(e.clone()));
+            }
+            if !BUILT_DRVS.lock().unwrap().contains(&self.drv_path) {
+                let status = Command::new("nix")
+                    .arg("build")
+                    .arg(build_path)
+                    .arg("--no-link")
+                    .args(get_nix_flags())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .status()?;
+                if !status.success() {
+                    return Err(std::io::Error::other(format!(
+                        "nix build {} failed with {}",
+                        build_path, status
+                    )));
+                }
+                BUILT_DRVS.lock().unwrap().insert(self.drv_path.clone());
+            }
             Ok(self.get_out_paths())
         })
     }
@@ -497,6 +648,108 @@ impl std::hash::Hash for Derivation {
 
 // impl Hash for Derivation {}
 
+/// Reads several derivations with a single `nix derivation show` call and adds them to the derivation cache.
+fn show_drvs(drv_paths: &[&str]) -> Option<HashMap<String, serde_json::Value>> {
+    let args = drv_paths.iter().map(|drv_path| {
+        if drv_path.ends_with(".drv") {
+            format!("{}^*", drv_path)
+        } else {
+            drv_path.to_string()
+        }
+    });
+    let output = Command::new("nix")
+        .arg("derivation")
+        .arg("show")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .ok()?;
+    let drvs: HashMap<String, serde_json::Value> = serde_json::from_reader(output.stdout?)
+        .inspect_err(|e| {
+            error!("Can not parse drvs {}: {}", drv_paths.join(" "), e);
+        })
+        .ok()?; // FIXME ?
+    DRV_CACHE
+        .lock()
+        .unwrap()
+        .extend(drvs.iter().map(|(k, v)| (k.clone(), v.clone())));
+    Some(drvs)
+}
+
+/// Fills the derivation cache for all given derivations at once.
+pub fn preload_drvs(drv_paths: &[String]) {
+    let missing: Vec<&str> = {
+        let cache = DRV_CACHE.lock().unwrap();
+        drv_paths
+            .iter()
+            .map(String::as_str)
+            .filter(|p| !cache.contains_key(*p))
+            .collect()
+    };
+    if !missing.is_empty() {
+        show_drvs(&missing);
+    }
+}
+
+/// Records a derivation as built, e.g. because its outputs are known to be in the store already.
+pub fn mark_built(drv_path: &str) {
+    BUILT_DRVS.lock().unwrap().insert(drv_path.to_owned());
+}
+
+/// Builds several derivations with a single `nix build` call, so nix can schedule them together.
+/// [`Derivation::build`] neither builds those whose outputs exist afterwards again, nor retries those that failed.
+pub fn build_all(drvs: &[&Derivation]) {
+    let drvs: Vec<&Derivation> = {
+        let built = BUILT_DRVS.lock().unwrap();
+        drvs.iter()
+            .filter(|d| !built.contains(&d.drv_path))
+            .copied()
+            .collect()
+    };
+    if drvs.is_empty() {
+        return;
+    }
+    let status = Command::new("nix")
+        .arg("build")
+        .args(drvs.iter().map(|d| format!("{}^*", d.drv_path)))
+        .arg("--no-link")
+        .arg("--keep-going")
+        .args(get_nix_flags())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .status();
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            error!("can not run nix build: {}", e);
+            return;
+        }
+    };
+    // with `--keep-going`, everything that could be built is, even if some derivations failed
+    let mut built = BUILT_DRVS.lock().unwrap();
+    let mut failed = FAILED_DRVS.lock().unwrap();
+    for drv in drvs {
+        let outputs: Vec<String> = drv.outputs.values().map(DrvOutput::path).collect();
+        if status.success() || outputs.iter().all(|o| Path::new(o).exists()) {
+            built.insert(drv.drv_path.clone());
+        } else if outputs.iter().all(|o| !o.is_empty()) {
+            failed.insert(
+                drv.drv_path.clone(),
+                format!("nix build {}^* failed with {}", drv.drv_path, status),
+            );
+        }
+    }
+}
+
+/// Archive formats the source scanners can unpack.
+const SUPPORTED_ARCHIVES: [&str; 4] = [".tar.gz", ".tgz", ".tar.xz", ".tar.bz2"];
+
+fn is_supported_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    SUPPORTED_ARCHIVES.iter().any(|ext| name.ends_with(ext))
+}
+
 fn try_extract_source_archive(src_archive_path: PathBuf) -> Option<TempDir> {
     let prefix = "nix-check-extract";
     let tmp_dir = tempfile::Builder::new().prefix(&prefix).tempdir().ok()?;
@@ -536,12 +789,28 @@ fn try_extract_source_archive(src_archive_path: PathBuf) -> Option<TempDir> {
     None
 }
 
-pub fn eval_attr_to_drv_path(attr: &str) -> Option<String> {
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluatedAttr {
+    /// attribute path relative to the evaluated installable, empty if it is a derivation itself
+    pub attr: String,
+    pub drv_path: String,
+    /// output name to store path
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+    /// `meta.position` of the package, `file:line`
+    #[serde(default)]
+    pub position: Option<String>,
+}
+
+/// Evaluates an installable to all derivations it contains, recursing into package sets.
+/// See `recurse-attrs.nix` for which attributes are considered.
+pub fn eval_attr_to_drv_paths(attr: &str) -> Option<Vec<EvaluatedAttr>> {
     let output = Command::new("nix")
         .arg("eval")
         .arg(attr)
         .arg("--apply")
-        .arg("attr: attr.drvPath")
+        .arg(include_str!("../recurse-attrs.nix"))
         .arg("--json")
         .args(get_nix_flags())
         .stdout(Stdio::piped())
@@ -551,6 +820,83 @@ pub fn eval_attr_to_drv_path(attr: &str) -> Option<String> {
     serde_json::from_reader(output.stdout?).ok()
 }
 
+/// Version of the nix used for all queries, e.g. `2.24.10`.
+pub fn nix_version() -> Option<String> {
+    let output = Command::new("nix")
+        .arg("--version")
+        .stderr(Stdio::inherit())
+        .output()
+        .ok()?;
+    // `nix (Nix) 2.24.10`
+    String::from_utf8(output.stdout)
+        .ok()?
+        .split_whitespace()
+        .last()
+        .map(str::to_owned)
+}
+
+/// Name of a store path without store directory, hash and `.drv` extension, e.g. `libXv-1.0.13`.
+pub fn drv_name(store_path: &str) -> String {
+    let name = store_path.strip_prefix("/nix/store/").unwrap_or(store_path);
+    let name = name.split_once('-').map_or(name, |(_, n)| n);
+    name.strip_suffix(".drv").unwrap_or(name).to_owned()
+}
+
+/// Splits a store path name like `builtins.parseDrvName`: the version starts at the first `-` not followed by a letter.
+pub fn parse_drv_name(name: &str) -> (&str, &str) {
+    name.char_indices()
+        .find(|(i, c)| {
+            *c == '-'
+                && name[i + 1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|n| !n.is_ascii_alphabetic())
+        })
+        .map_or((name, ""), |(i, _)| (&name[..i], &name[i + 1..]))
+}
+
 pub fn get_store_hash(store_path: &str) -> String {
     store_path.strip_prefix("/nix/store/").unwrap_or(store_path)[..32].to_owned()
 }
+
+/// Maps a path inside the nix store (or a symlink into it, like `./result`) to its top-level store path.
+pub fn to_store_path(path: &str) -> Option<String> {
+    let canonical = fs::canonicalize(path).ok()?;
+    let relative = canonical.strip_prefix("/nix/store").ok()?;
+    let name = relative.components().next()?.as_os_str().to_str()?;
+    Some(format!("/nix/store/{}", name))
+}
+
+pub fn query_deriver(store_path: &str) -> Option<String> {
+    query_derivers(&[store_path.to_owned()]).pop().flatten()
+}
+
+/// Derivers of several store paths with a single `nix-store` call, in the same order as the given paths.
+pub fn query_derivers(store_paths: &[String]) -> Vec<Option<String>> {
+    if store_paths.is_empty() {
+        return Vec::new();
+    }
+    // nix-store --query --deriver /nix/store/wfmrzq8bkjr1mvjkjbvqf9vg4n0bvz2c-libvlc-3.0.21
+    let Ok(output) = Command::new("nix-store")
+        .arg("--query")
+        .arg("--deriver")
+        .args(store_paths)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+    else {
+        return vec![None; store_paths.len()];
+    };
+    if !output.status.success() {
+        return vec![None; store_paths.len()];
+    }
+    output
+        .stdout
+        .lines()
+        .map(|l| {
+            l.ok()
+                .map(|d| d.trim().to_owned())
+                .filter(|d| !d.is_empty() && d != "unknown-deriver")
+        })
+        .collect()
+}
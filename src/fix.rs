@@ -0,0 +1,184 @@
+use log::error;
+use similar::TextDiff;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    nixfile::{self, NixFile},
+    report::{FindingClass, RootReport, SourcePosition, UnusedDep},
+};
+
+/// The file to edit for a nix file that was evaluated, `None` if it was copied to the store and no checkout is given.
+fn local_file(position: &SourcePosition, nixpkgs: Option<&Path>) -> Option<PathBuf> {
+    match position.source_relative_file() {
+        Some(relative) => Some(nixpkgs?.join(relative)),
+        None => Some(PathBuf::from(&position.file)),
+    }
+}
+
+/// Removes the unused dependencies of one root from `source`, and the arguments of the `callPackage` header only they used.
+/// Returns the new source and whether every dependency was found.
+fn remove_unused(report: &RootReport, path: &str, source: &str) -> (String, bool) {
+    // known runtime-only dependencies need to be verified by hand first
+    let deps: Vec<&UnusedDep> = report
+        .unused
+        .iter()
+        .filter(|d| d.class() == FindingClass::Unused)
+        .collect();
+    let names: Vec<Vec<String>> = deps
+        .iter()
+        .map(|d| nixfile::dependency_names(&d.drv_path, d.pname.as_deref(), &d.attrs))
+        .collect();
+    let line = report.position.as_ref().map(|p| p.line);
+    let removal = NixFile::parse(path, source.to_owned()).remove_build_inputs(line, &names);
+    for &i in &removal.missing {
+        error!(
+            "can not find {} in the buildInputs of {}",
+            deps[i].drv_path, path
+        );
+    }
+    let mut source = removal.source;
+    // the header is shared by every package of the file
+    if removal.only_package {
+        for name in removal.references {
+            if let Some(removed) =
+                NixFile::parse(path, source.clone()).remove_unused_argument(&name)
+            {
+                source = removed;
+            }
+        }
+    }
+    (source, removal.missing.is_empty())
+}
+
+/// Prints a unified diff per root that removes its unused dependencies, and applies it if `write` is set.
+/// Roots defined in the same file see the changes made for earlier ones.
+/// Returns whether every unused dependency could be removed.
+pub fn fix(reports: &[RootReport], nixpkgs: Option<&Path>, write: bool) -> bool {
+    let mut complete = true;
+    let mut edited: HashMap<PathBuf, String> = HashMap::new();
+    for report in reports.iter().filter(|r| !r.unused.is_empty()) {
+        let Some(position) = &report.position else {
+            error!(
+                "nix file of {} is unknown, scan it from an attribute",
+                report.display_name()
+            );
+            complete = false;
+            continue;
+        };
+        let Some(path) = local_file(position, nixpkgs) else {
+            error!(
+                "{} is in the nix store, pass --nixpkgs to edit a local checkout",
+                position.file
+            );
+            complete = false;
+            continue;
+        };
+        let source = match edited.get(&path) {
+            Some(source) => source.clone(),
+            None => match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("can not read {}: {}", path.display(), e);
+                    complete = false;
+                    continue;
+                }
+            },
+        };
+
+        let (fixed, found_all) = remove_unused(report, &path.to_string_lossy(), &source);
+        complete &= found_all;
+        if fixed == source {
+            continue;
+        }
+        if let Some(e) = rnix::Root::parse(&fixed).errors().first() {
+            error!(
+                "not changing {}, removing the unused dependencies of {} breaks its syntax: {}",
+                path.display(),
+                report.display_name(),
+                e
+            );
+            complete = false;
+            continue;
+        }
+        // paths relative to the checkout, so the diff applies with `git apply`
+        let file = position.workspace_file();
+        print!(
+            "{}",
+            TextDiff::from_lines(&source, &fixed)
+                .unified_diff()
+                .header(&format!("a/{}", file), &format!("b/{}", file))
+        );
+        if write {
+            if let Err(e) = fs::write(&path, &fixed) {
+                error!("can not write {}: {}", path.display(), e);
+                complete = false;
+            }
+        }
+        edited.insert(path, fixed);
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"{
+  lib,
+  stdenv,
+  zlib,
+  dbus,
+  libXv,
+}:
+
+stdenv.mkDerivation {
+  pname = "foo";
+  buildInputs = [
+    zlib
+    dbus
+    libXv
+  ];
+  meta.license = lib.licenses.mit;
+}
+"#;
+
+    fn report(unused: serde_json::Value) -> RootReport {
+        serde_json::from_value(serde_json::json!({
+            "root": "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-foo-1.drv",
+            "position": { "file": "package.nix", "line": 10 },
+            "unused": unused,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn removes_inputs_and_their_arguments() {
+        let report = report(serde_json::json!([
+            { "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-libXv-1.0.drv" },
+            {
+                "drv_path": "/nix/store/cccccccccccccccccccccccccccccccc-dbus-1.14.drv",
+                "known_runtime": { "reason": "dbus", "verify": "dbus" },
+            },
+        ]));
+        let (fixed, complete) = remove_unused(&report, "package.nix", SOURCE);
+        assert!(complete);
+        assert_eq!(
+            fixed,
+            SOURCE.replace("  libXv,\n", "").replace("    libXv\n", "")
+        );
+    }
+
+    #[test]
+    fn reports_missing_inputs() {
+        let report = report(serde_json::json!([
+            { "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-openssl-3.0.drv" },
+        ]));
+        let (fixed, complete) = remove_unused(&report, "package.nix", SOURCE);
+        assert!(!complete);
+        assert_eq!(fixed, SOURCE);
+    }
+}
@@ -0,0 +1,73 @@
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::{config::Pattern, report::KnownRuntime};
+
+/// Format version of `known-runtime-deps.toml` this build understands.
+const SUPPORTED_VERSION: u32 = 1;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct KnownDep {
+    /// matched against the drv path of the dependency
+    pattern: Pattern,
+    reason: String,
+    verify: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct KnownDeps {
+    version: u32,
+    dependency: Vec<KnownDep>,
+}
+
+static KNOWN_DEPS: Lazy<KnownDeps> = Lazy::new(|| {
+    let known: KnownDeps = toml::from_str(include_str!("../known-runtime-deps.toml"))
+        .expect("shipped known-runtime-deps.toml is valid");
+    assert_eq!(
+        known.version, SUPPORTED_VERSION,
+        "unsupported known-runtime-deps.toml version"
+    );
+    known
+});
+
+/// Looks up whether a dependency is known to be used in ways the scanners can not see.
+pub fn lookup(dep_drv_path: &str) -> Option<KnownRuntime> {
+    KNOWN_DEPS
+        .dependency
+        .iter()
+        .find(|k| k.pattern.0.is_match(dep_drv_path))
+        .map(|k| KnownRuntime {
+            reason: k.reason.clone(),
+            verify: k.verify.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_patterns_parse() {
+        let known: KnownDeps = toml::from_str(include_str!("../known-runtime-deps.toml")).unwrap();
+        assert_eq!(known.version, SUPPORTED_VERSION);
+        assert!(!known.dependency.is_empty());
+    }
+
+    #[test]
+    fn lookups() {
+        for name in [
+            "dbus-1.14.10",
+            "dbus-glib-0.112",
+            "polkit-124",
+            "systemd-minimal-libs-256.8",
+            "wayland-protocols-1.38",
+            "dconf-0.40.0",
+        ] {
+            let drv_path = format!("/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-{}.drv", name);
+            assert!(lookup(&drv_path).is_some(), "{}", name);
+        }
+        assert!(lookup("/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-zlib-1.3.1.drv").is_none());
+    }
+}
@@ -1,224 +1,549 @@
+mod advisories;
 mod args;
+mod attrindex;
+mod baseline;
+mod closure;
+mod config;
 mod derivation;
-use crate::args::Cli;
+mod fix;
+mod known;
+mod nixfile;
+mod output;
+mod report;
+mod runstate;
+mod scan;
+mod scope;
+mod stats;
+mod targets;
+use crate::args::{Cli, Command};
+use advisories::Advisories;
+use attrindex::AttrIndex;
+use baseline::Baseline;
 use clap::Parser;
+use closure::ClosureIndex;
+use config::Config;
 use derivation::Derivation;
-use ignore::Walk;
-use log::info;
+use log::{error, warn};
 use once_cell::sync::OnceCell;
+use output::{Format, RunInfo};
+use report::{
+    Failure, FailureKind, FilterReason, FilteredDep, InputCategory, RootReport, SourcePosition,
+    UnusedDep,
+};
+use runstate::RunState;
+use scan::ScanOptions;
+use scope::{ClosureKind, Scope};
 // use rayon::ThreadPoolBuilder;
-use regex::Regex;
-use serde_json::json;
-use std::{collections::HashMap, path::Path, time::Instant};
-
-use grep::{
-    regex::RegexMatcher,
-    searcher::{sinks::Bytes, BinaryDetection, Searcher},
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
 };
 
-use std::fs::{self};
+/// exit code if unused dependencies (not in the baseline) were found
+const EXIT_UNUSED_FOUND: i32 = 3;
+/// exit code if no unused dependencies were found, but some roots could not be scanned completely
+const EXIT_INCOMPLETE: i32 = 4;
+
+/// exit code if the run was stopped by SIGINT/SIGTERM, like shells report a SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Roots built with a single `nix build` call before they are scanned.
+const BUILD_BATCH: usize = 32;
 
 static NIX_FLAGS: OnceCell<Vec<String>> = OnceCell::new();
 
+/// set on the first SIGINT/SIGTERM, no new roots are scanned after
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
 pub fn get_nix_flags() -> &'static Vec<String> {
     NIX_FLAGS.get_or_init(Vec::new)
 }
 
 fn main() {
     env_logger::init();
-    let permitted_unused_deps = vec![
-        Regex::new("iconv-").unwrap(),
-        Regex::new("gtest-").unwrap(),
-        Regex::new("gbenchmark-").unwrap(),
-        Regex::new("wayland-protocols").unwrap(),
-        Regex::new("-dbus").unwrap(),
-        Regex::new("-polkit").unwrap(),
-        Regex::new("-systemd").unwrap(),
-        Regex::new("python3\\..*-mock-").unwrap(),
-        Regex::new("python3\\..*-pytest-").unwrap(),
-        Regex::new("perl-?5\\.").unwrap(),
-        Regex::new("-check-").unwrap(),
-        Regex::new(r"-hook(\.drv(\^\**)?)?$").unwrap(),
-    ];
-
+    let started_at = SystemTime::now();
     let cli = Cli::parse();
-    let attr: String = cli.attr.to_string();
-    NIX_FLAGS.set(cli.nix_flags.unwrap_or_else(Vec::new)).ok();
+    NIX_FLAGS
+        .set(cli.nix_flags.clone().unwrap_or_default())
+        .ok();
 
-    let drv_logic = attr.ends_with(".drv") && Path::new(&attr).exists();
-    let attr = if drv_logic {
-        attr
-    } else {
-        derivation::eval_attr_to_drv_path(&attr).unwrap()
-    };
+    if let Err(e) = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+        warn!("interrupted, stopping after the current root, interrupt again to quit immediately");
+    }) {
+        warn!("can not handle interrupts: {}", e);
+    }
 
-    let drv = Derivation::read_drv(&attr).unwrap();
+    let format = cli.format();
+    // an update needs all findings first, otherwise findings are suppressed as soon as they are printed
+    let baseline = cli
+        .baseline
+        .as_ref()
+        .filter(|_| !cli.update_baseline)
+        .map(|path| {
+            Baseline::read(path).unwrap_or_else(|e| {
+                error!("can not use baseline {}: {}", path.display(), e);
+                std::process::exit(1);
+            })
+        });
+    let attr_index = cli.attr_index.as_ref().map(|path| {
+        AttrIndex::read(path).unwrap_or_else(|e| {
+            error!("can not read attribute index {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
+    let stream = format == Format::Jsonl && !cli.stats;
+    let mut on_report = |report: &RootReport| {
+        if stream {
+            let mut report = report.clone();
+            if let Some(baseline) = &baseline {
+                baseline.suppress(std::slice::from_mut(&mut report));
+            }
+            output::jsonl::print_root(&report);
+        }
+    };
 
-    // [ ( dependent, [ dependency ] ) ]
-    let mut scan_roots: Vec<(Derivation, Vec<Derivation>)> = Vec::new();
+    let (mut reports, failures) = match &cli.command {
+        Some(Command::Merge { state_files }) => {
+            let mut reports = merge_state_files(state_files);
+            if let Some(index) = &attr_index {
+                for report in reports.iter_mut() {
+                    index.annotate(report);
+                    // attribute paths name dependencies the scan could not find
+                    nixfile::locate_declarations(report);
+                }
+            }
+            reports.iter().for_each(&mut on_report);
+            (reports, Vec::new())
+        }
+        Some(Command::Fix {
+            dry_run: _,
+            write,
+            nixpkgs,
+            state_files,
+        }) => {
+            let mut reports = merge_state_files(state_files);
+            if let Some(index) = &attr_index {
+                reports.iter_mut().for_each(|r| index.annotate(r));
+            }
+            if fix::fix(&reports, nixpkgs.as_deref(), *write) {
+                return;
+            }
+            std::process::exit(EXIT_INCOMPLETE);
+        }
+        Some(Command::Index { installable }) => {
+            let Some(entries) = attrindex::build(installable) else {
+                error!("can not evaluate {}", installable);
+                std::process::exit(1);
+            };
+            for entry in entries {
+                println!("{}", serde_json::to_string(&entry).unwrap());
+            }
+            return;
+        }
+        Some(Command::Schema) => {
+            println!("{}", output::json::schema());
+            return;
+        }
+        None => scan(&cli, attr_index.as_ref(), &mut on_report),
+    };
 
-    if cli.tree {
-        for drv in drv.get_requisites() {
-            let deps = drv.read_deps();
-            scan_roots.push((drv, deps));
+    if let Some(baseline) = &baseline {
+        baseline.suppress(&mut reports);
+    } else if let Some(path) = cli.baseline.as_ref().filter(|_| cli.update_baseline) {
+        let baseline = Baseline::from_reports(&reports);
+        if interrupted() {
+            warn!("not updating the baseline of an interrupted run");
+        } else if let Err(e) = baseline.write(path) {
+            error!("can not write baseline {}: {}", path.display(), e);
+            std::process::exit(1);
         }
+        // after an update, everything found is part of the baseline
+        baseline.suppress(&mut reports);
     }
 
-    let deps = drv.read_deps();
-    scan_roots.insert(0, (drv, deps)); // insert top-level at the start so the nix build can build all dependents at once
+    if reports.iter().any(|r| {
+        r.unused
+            .iter()
+            .any(|d| d.closure_bytes.is_some() || !d.advisories.is_empty())
+    }) {
+        report::rank_by_savings(&mut reports);
+    }
 
-    // let pool = ThreadPoolBuilder::new()
-    //     .num_threads(cli.jobs)
-    //     .build()
-    //     .unwrap();
+    let run = RunInfo {
+        started_at,
+        interrupted: interrupted(),
+    };
+    output::print_report(&reports, &failures, format, cli.stats, &run);
 
-    let skipped: Vec<String> = cli.skip.split(",").map(str::to_owned).collect();
+    if run.interrupted {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    std::process::exit(exit_code(&reports, &failures));
+}
 
-    let mut found_unused: HashMap<String, Vec<String>> = HashMap::new();
+/// Combines the root reports of several state files, later files win for roots scanned more than once.
+fn merge_state_files(state_files: &[PathBuf]) -> Vec<RootReport> {
+    let mut merged: HashMap<String, RootReport> = HashMap::new();
+    for path in state_files {
+        let reports = runstate::read_state_file(path).unwrap_or_else(|e| {
+            error!("can not read state file {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        merged.extend(reports.into_iter().map(|r| (r.root.clone(), r)));
+    }
+    let mut reports: Vec<RootReport> = merged.into_values().collect();
+    reports.sort_by(|a, b| a.root.cmp(&b.root));
+    reports
+}
 
-    // FIXME: this doesn't really check in parallel, this never worked in the first place
-    // pool.install(|| {
-    scan_roots.iter_mut().for_each(|(root, dep_relations)| {
-        if skipped.iter().any(|s| root.matches_pname(s)) {
-            return;
-        }
+/// Resolves all targets and scans them, returns the reports of all roots and targets that failed to evaluate.
+/// `on_report` is called for every root as soon as its report is complete.
+fn scan(
+    cli: &Cli,
+    attr_index: Option<&AttrIndex>,
+    on_report: &mut impl FnMut(&RootReport),
+) -> (Vec<RootReport>, Vec<Failure>) {
+    // the runtime closure is read from the built outputs at once, it has no levels
+    if cli.closure == ClosureKind::Runtime && cli.max_depth.is_some() {
+        error!("--max-depth only applies to --closure build");
+        std::process::exit(1);
+    }
+    let config = Config::load(cli.config.as_deref(), !cli.no_default_config).unwrap_or_else(|e| {
+        error!("can not load configuration: {}", e);
+        std::process::exit(1);
+    });
+    let advisories = cli.advisories.as_ref().map(|path| {
+        Advisories::read(path).unwrap_or_else(|e| {
+            error!("can not read advisories {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
 
-        // debug!("rels {:?}", dep_relations);
-        // debug!("root {:?}", root.drv_path);
+    let mut state = cli.state.as_ref().map(|path| {
+        RunState::open(path, cli.resume).unwrap_or_else(|e| {
+            error!("can not open state file {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    });
 
-        dep_relations.retain(|dep_drv| {
-            !permitted_unused_deps
-                .iter()
-                .any(|re| re.is_match(&dep_drv.drv_path))
+    let mut targets = Vec::new();
+    if let Some(attr) = &cli.attr {
+        targets.extend(targets::parse_targets(attr));
+    }
+    if let Some(target_file) = cli.targets.clone() {
+        let contents = target_file.contents().unwrap_or_else(|e| {
+            error!("can not read targets: {}", e);
+            std::process::exit(1);
         });
+        targets.extend(targets::parse_targets(&contents));
+    }
 
-        if cli.check_headers || cli.list_used_headers {
-            let start = Instant::now();
-            let used_headers = root.find_used_c_headers();
-            dep_relations.retain(|dep_drv| {
-                !dep_drv
-                    .get_provided_c_headers()
-                    .intersection(&used_headers)
-                    .any(|_| true)
-            });
-            if cli.list_used_headers {
-                for header in used_headers {
-                    info!("{} uses header: {}", root.drv_path, header);
-                }
-            }
-            info!("check-headers took {:.2?} seconds", start.elapsed());
+    let attr_filter = targets::AttrFilter {
+        include: cli.include.clone(),
+        exclude: cli.exclude.clone(),
+    };
+    let mut drv_paths: Vec<String> = Vec::new();
+    let mut seen_drv_paths: HashSet<String> = HashSet::new();
+    let mut root_attrs: HashMap<String, String> = HashMap::new();
+    let mut root_positions: HashMap<String, SourcePosition> = HashMap::new();
+    let mut eval_failures: Vec<Failure> = Vec::new();
+    let mut resolved: Vec<targets::Target> = Vec::new();
+    for target in targets.iter().take_while(|_| !interrupted()) {
+        match target.resolve(&attr_filter) {
+            Some(r) => resolved.extend(r),
+            None => eval_failures.push(Failure {
+                kind: FailureKind::Evaluation,
+                message: format!(
+                    "can not resolve {}",
+                    target.attr.as_deref().unwrap_or_default()
+                ),
+            }),
+        }
+    }
+    for target in resolved {
+        let Some(drv_path) = target.drv_path else {
+            continue;
+        };
+        if !seen_drv_paths.insert(drv_path.clone()) {
+            continue;
+        }
+        // outputs provided by e.g. nix-eval-jobs that already exist don't need to be built again
+        if !target.outputs.is_empty() && target.outputs.iter().all(|o| Path::new(o).exists()) {
+            derivation::mark_built(&drv_path);
         }
+        if let Some(attr) = target.attr {
+            root_attrs.insert(drv_path.clone(), attr);
+        }
+        if let Some(position) = target
+            .position
+            .as_deref()
+            .and_then(SourcePosition::from_meta_position)
+        {
+            root_positions.insert(drv_path.clone(), position);
+        }
+        drv_paths.push(drv_path);
+    }
+    if drv_paths.is_empty() {
+        error!("no derivations to check");
+        // targets that failed to evaluate make the run incomplete, not invalid
+        if !eval_failures.is_empty() {
+            return (Vec::new(), eval_failures);
+        }
+        std::process::exit(1);
+    }
+    derivation::preload_drvs(&drv_paths);
 
-        if cli.skip_dep_usage_check {
-            return;
+    let mut drvs: Vec<Derivation> = Vec::new();
+    for drv_path in &drv_paths {
+        match Derivation::read_drv(drv_path) {
+            Some(drv) => drvs.push(drv),
+            // e.g. a stale `drvPath` of nix-eval-jobs output
+            None => eval_failures.push(Failure {
+                kind: FailureKind::Evaluation,
+                message: format!("can not read derivation {}", drv_path),
+            }),
         }
+    }
+    if drvs.is_empty() {
+        return (Vec::new(), eval_failures);
+    }
 
-        if cli.check_pyproject {
-            let start = Instant::now();
-            let used_py_deps = root.find_used_pyproject_deps();
-            dep_relations
-                .retain(|dep_drv| !used_py_deps.iter().any(|py| dep_drv.matches_pname(py)));
-            info!("check-pyproject took {:.2?} seconds", start.elapsed());
+    let mut seen_roots: HashSet<String> = drvs.iter().map(|d| d.drv_path.clone()).collect();
+    let scope = Scope {
+        closure: if cli.system {
+            ClosureKind::Runtime
+        } else {
+            cli.closure
+        },
+        max_depth: cli.max_depth,
+        only_with_build_inputs: cli.only_with_build_inputs,
+        exclude_fixed_output: cli.exclude_fixed_output,
+        pname: cli.pname_pattern.clone(),
+    };
+    let mut requisites = Vec::new();
+    if cli.tree || cli.system {
+        for drv in &drvs {
+            for req in scope.requisites(drv) {
+                if seen_roots.insert(req.drv_path.clone()) {
+                    requisites.push(req);
+                }
+            }
         }
+    }
 
-        if cli.check_shebangs {
-            let start = Instant::now();
-            let used_shebangs = root.find_used_shebangs();
-            dep_relations.retain(|dep_drv| {
-                !dep_drv
-                    .get_provided_binaries()
-                    .intersection(&used_shebangs)
-                    .any(|_| true)
-            });
-            info!("check-shebangs took {:.2?} seconds", start.elapsed());
+    // top-level roots go first so their results show up before the transitive ones
+    let roots: Vec<Derivation> = drvs
+        .into_iter()
+        .chain(requisites)
+        .filter(|d| cli.shard.is_none_or(|s| s.contains(&d.drv_path)))
+        .filter(|d| !state.as_ref().is_some_and(|s| s.is_completed(&d.drv_path)))
+        .collect();
+
+    // in system mode savings are measured against everything the given systems ship
+    let system_outputs: Option<Vec<String>> = cli.system.then(|| {
+        drv_paths
+            .iter()
+            .flat_map(|p| Derivation::read_drv(p).into_iter())
+            .flat_map(|d| d.get_out_paths())
+            .collect()
+    });
+    let mut closure_index = ClosureIndex::default();
+
+    // [ ( dependent, [ dependency ], [ unchecked input ] ) ]
+    let mut scan_roots: Vec<(Derivation, Vec<Derivation>, Vec<FilteredDep>)> = Vec::new();
+    for drv in roots {
+        let (deps, filtered) = drv.read_deps();
+        scan_roots.push((drv, deps, filtered));
+    }
+
+    // let pool = ThreadPoolBuilder::new()
+    //     .num_threads(cli.jobs)
+    //     .build()
+    //     .unwrap();
+
+    let skipped: Vec<String> = cli.skip.split(",").map(str::to_owned).collect();
+
+    let scan_options = ScanOptions {
+        check_headers: cli.check_headers && config.scanners.headers.unwrap_or(true),
+        list_used_headers: cli.list_used_headers,
+        skip_dep_usage_check: cli.skip_dep_usage_check,
+        check_pyproject: cli.check_pyproject && config.scanners.pyproject.unwrap_or(true),
+        check_shebangs: cli.check_shebangs && config.scanners.shebangs.unwrap_or(true),
+        check_shared_objects: cli.check_shared_objects
+            && config.scanners.shared_objects.unwrap_or(true),
+    };
+
+    // findings of earlier runs go first, they were found first
+    let mut resumed: Vec<RootReport> = state.iter().flat_map(|s| s.completed().cloned()).collect();
+    resumed.sort_by(|a, b| a.root.cmp(&b.root));
+    resumed.iter().for_each(&mut *on_report);
+
+    let mut reports: Vec<RootReport> = Vec::new();
+
+    // FIXME: this doesn't really check in parallel, this never worked in the first place
+    // pool.install(|| {
+    'roots: for batch in scan_roots.chunks_mut(BUILD_BATCH) {
+        if interrupted() {
+            break;
         }
+        // nix schedules the builds of a batch together, while reports still stream out root by root
+        derivation::build_all(&batch.iter().map(|(root, _, _)| root).collect::<Vec<_>>());
+        for (root, dep_relations, filtered) in batch.iter_mut() {
+            if interrupted() {
+                break 'roots;
+            }
+            let attr = root_attrs.get(&root.drv_path).map(String::as_str);
+            if skipped.iter().any(|s| root.matches_pname(s)) || config.is_skipped(root, attr) {
+                continue;
+            }
+
+            // debug!("rels {:?}", dep_relations);
+            // debug!("root {:?}", root.drv_path);
 
-        if cli.check_shared_objects {
-            let start = Instant::now();
-            let used_shared_objects = root.find_used_shared_objects();
             dep_relations.retain(|dep_drv| {
-                !dep_drv
-                    .find_provided_shared_objects()
-                    .intersection(&used_shared_objects)
-                    .any(|_| true)
+                let Some(reason) = config.allowed_reason(root, attr, &dep_drv.drv_path) else {
+                    return true;
+                };
+                filtered.push(FilteredDep {
+                    detail: Some(reason.to_owned()),
+                    ..FilteredDep::new(&dep_drv.drv_path, FilterReason::Allowlisted)
+                });
+                false
             });
-            info!("check-shared-objects took {:.2?} seconds", start.elapsed());
-        }
 
-        // make sure the package exists in local store so it can be scanned
-        let pkg_outputs = if let Ok(pkg_outputs) = root.build() {
-            pkg_outputs
-        } else {
-            log::error!(
-                "derivation {} does not build, skipping checks...",
-                root.drv_path
-            );
-            return;
-        };
+            let Some(outcome) = scan::scan_root(root, std::mem::take(dep_relations), &scan_options)
+            else {
+                continue;
+            };
+            // nix calls of the scan were interrupted as well, so its results can't be trusted
+            if interrupted() {
+                break 'roots;
+            }
 
-        let mut searcher = Searcher::new();
-        searcher.set_binary_detection(BinaryDetection::none());
-        for output in pkg_outputs {
-            for e in Walk::new(output).flat_map(Result::into_iter) {
-                let is_file = e.file_type().is_some_and(|f| f.is_file());
-                let is_link = e.file_type().is_some_and(|f| f.is_symlink());
-
-                if is_file {
-                    dep_relations.retain(|dep_drv| {
-                        let mut found = false;
-                        let regex: String = dep_drv
-                            .get_out_paths()
-                            .iter()
-                            .map(|dep| derivation::get_store_hash(dep))
-                            .collect::<Vec<String>>()
-                            .join("|");
-                        let matcher = RegexMatcher::new(&regex).unwrap();
-                        searcher
-                            .search_path(
-                                &matcher,
-                                e.path(),
-                                Bytes(|_, _| {
-                                    found = true;
-                                    Ok(false) // stop reading the file
-                                }),
-                            )
-                            .ok();
-                        !found
-                    });
-                } else if is_link {
-                    dep_relations.retain(|dep_drv| {
-                        let p = fs::read_link(e.path()).unwrap();
-                        for dep in dep_drv.get_out_paths() {
-                            if p.to_string_lossy()
-                                .contains(&derivation::get_store_hash(&dep))
-                            {
-                                return false;
-                            }
-                        }
-                        true
-                    });
+            let mut found_unused_drv = Vec::new();
+            for dep in outcome.unused.iter() {
+                // what leaves the closure of the root without the dependency
+                // i.e. the runtime closure of its outputs
+                let exclusive_paths = if let Some(kept) = &system_outputs {
+                    closure_index.exclusive_paths(&dep.get_out_paths(), kept)
+                } else if cli.closure_size || advisories.is_some() {
+                    closure_index.exclusive_paths(&dep.get_out_paths(), &root.get_out_paths())
+                } else {
+                    None
+                };
+                let closure_bytes = exclusive_paths
+                    .as_deref()
+                    .filter(|_| cli.system || cli.closure_size)
+                    .map(|paths| closure_index.size(paths));
+                found_unused_drv.push(UnusedDep {
+                    drv_path: dep.drv_path.clone(),
+                    pname: dep.pname().map(str::to_owned),
+                    outputs: dep.get_outputs(),
+                    input_category: InputCategory::BuildInputs,
+                    position: None,
+                    attrs: Vec::new(),
+                    closure_bytes,
+                    advisories: advisories
+                        .as_ref()
+                        .map(|a| a.for_dep(dep, exclusive_paths.as_deref()))
+                        .unwrap_or_default(),
+                    known_runtime: known::lookup(&dep.drv_path),
+                });
+            }
+            let mut report = RootReport {
+                root: root.drv_path.clone(),
+                attr: root_attrs.get(&root.drv_path).cloned(),
+                pname: root.pname().map(str::to_owned),
+                version: root.version().map(str::to_owned),
+                position: root_positions.get(&root.drv_path).cloned(),
+                unused: found_unused_drv,
+                used: outcome.used,
+                scanners: outcome.scanners,
+                filtered: std::mem::take(filtered),
+                failures: outcome.failures,
+            };
+            if let Some(index) = attr_index {
+                index.annotate(&mut report);
+            }
+            nixfile::locate_declarations(&mut report);
+            let build_failed = report.failures.iter().any(|f| f.kind == FailureKind::Build);
+            // roots that failed to build are not recorded, so resuming retries them
+            if let Some(state) = state.as_mut().filter(|_| !build_failed) {
+                if let Err(e) = state.record(&report) {
+                    error!("can not write state file: {}", e);
                 }
             }
+            on_report(&report);
+            reports.push(report);
         }
-
-        let mut found_unused_drv = Vec::new();
-        for dep in dep_relations.iter() {
-            found_unused_drv.push(dep.drv_path.clone());
-            // fixme: json
-        }
-        if !found_unused_drv.is_empty() {
-            found_unused.insert(root.drv_path.clone(), found_unused_drv);
-        }
-    });
+    }
     // });
 
-    if cli.json {
-        println!("{}", json!(found_unused));
+    reports.splice(0..0, resumed);
+
+    (reports, eval_failures)
+}
+
+/// Exit code for CI: new findings take precedence over an incomplete scan.
+fn exit_code(reports: &[RootReport], failures: &[Failure]) -> i32 {
+    if reports.iter().any(|r| !r.unused.is_empty()) {
+        EXIT_UNUSED_FOUND
+    } else if !failures.is_empty() || reports.iter().any(|r| !r.failures.is_empty()) {
+        EXIT_INCOMPLETE
     } else {
-        for (root, unused) in found_unused {
-            for dep in unused {
-                println!("{} has unused dependency: {}", root, dep);
-            }
-        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report(unused: bool, failed: bool) -> RootReport {
+        serde_json::from_value(json!({
+            "root": "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-mpv-0.38.drv",
+            "unused": if unused {
+                vec![json!({ "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-dbus-1.14.drv" })]
+            } else {
+                vec![]
+            },
+            "failures": if failed {
+                vec![json!({ "kind": "source-extraction", "message": "can not unpack" })]
+            } else {
+                vec![]
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn exit_codes() {
+        let eval_failure = Failure {
+            kind: FailureKind::Evaluation,
+            message: "can not evaluate nixpkgs#broken".to_owned(),
+        };
+        assert_eq!(exit_code(&[report(false, false)], &[]), 0);
+        assert_eq!(exit_code(&[], &[]), 0);
+        assert_eq!(exit_code(&[report(false, true)], &[]), EXIT_INCOMPLETE);
+        assert_eq!(
+            exit_code(&[report(false, false)], std::slice::from_ref(&eval_failure)),
+            EXIT_INCOMPLETE
+        );
+        assert_eq!(exit_code(&[report(true, false)], &[]), EXIT_UNUSED_FOUND);
+        // findings take precedence over an incomplete scan
+        assert_eq!(
+            exit_code(&[report(false, true), report(true, false)], &[eval_failure]),
+            EXIT_UNUSED_FOUND
+        );
     }
 }
@@ -0,0 +1,592 @@
+use log::debug;
+use rnix::{NodeOrToken, Root, SyntaxKind, SyntaxNode, TextSize};
+use std::{fs, io, ops::Range};
+
+use crate::{
+    derivation::{drv_name, parse_drv_name},
+    report::{RootReport, SourcePosition},
+};
+
+/// Attributes dependencies are declared in, `buildInputs` first since only those are checked.
+const INPUT_ATTRS: [&str; 6] = [
+    "buildInputs",
+    "propagatedBuildInputs",
+    "nativeBuildInputs",
+    "propagatedNativeBuildInputs",
+    "checkInputs",
+    "nativeCheckInputs",
+];
+
+/// Result of removing dependencies from the `buildInputs` of a package.
+pub struct Removal {
+    pub source: String,
+    /// variables the removed elements referred to
+    pub references: Vec<String>,
+    /// indices of the dependencies no element was found for
+    pub missing: Vec<usize>,
+    /// whether the package is the only one of the file, so its `callPackage` header may be changed
+    pub only_package: bool,
+}
+
+/// A parsed nix file, to find where the dependencies of a package are declared.
+pub struct NixFile {
+    path: String,
+    source: String,
+    root: SyntaxNode,
+}
+
+/// Identifiers compare equal regardless of case and punctuation, so `gtk+3` matches `gtk3`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Whether any identifier in `node` is one of the already normalized `names`, e.g. `libXv` in `xorg.libXv`.
+fn mentions(node: &SyntaxNode, names: &[String]) -> bool {
+    node.descendants_with_tokens()
+        .filter_map(|e| e.into_token())
+        .filter(|t| t.kind() == SyntaxKind::TOKEN_IDENT)
+        .any(|t| names.contains(&normalize(t.text())))
+}
+
+/// Name of the last attribute of a binding, `buildInputs` for both `buildInputs = ..` and `env.buildInputs = ..`.
+fn binding_name(binding: &SyntaxNode) -> Option<String> {
+    let attrpath = binding
+        .children()
+        .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)?;
+    let last = attrpath.children().last()?;
+    (last.kind() == SyntaxKind::NODE_IDENT).then(|| last.text().to_string())
+}
+
+/// Variables `node` refers to, i.e. identifiers that are not attribute names or function arguments.
+fn references(node: &SyntaxNode) -> impl Iterator<Item = String> {
+    node.descendants()
+        .filter(|n| n.kind() == SyntaxKind::NODE_IDENT)
+        .filter(|n| {
+            !n.parent().is_some_and(|p| {
+                matches!(
+                    p.kind(),
+                    SyntaxKind::NODE_ATTRPATH
+                        | SyntaxKind::NODE_PAT_ENTRY
+                        | SyntaxKind::NODE_PAT_BIND
+                        | SyntaxKind::NODE_IDENT_PARAM
+                )
+            })
+        })
+        .map(|n| n.text().to_string())
+}
+
+/// Bindings of dependency lists in `scope`, e.g. `buildInputs = [ ... ]`.
+fn input_bindings(scope: &SyntaxNode) -> impl Iterator<Item = (String, SyntaxNode)> {
+    scope
+        .descendants()
+        .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+        .filter_map(|n| Some((binding_name(&n)?, n)))
+        .filter(|(name, _)| INPUT_ATTRS.contains(&name.as_str()))
+}
+
+fn contains(outer: &SyntaxNode, inner: &SyntaxNode) -> bool {
+    outer.text_range().contains_range(inner.text_range())
+}
+
+fn node_range(node: &SyntaxNode) -> Range<usize> {
+    usize::from(node.text_range().start())..usize::from(node.text_range().end())
+}
+
+/// Names a dependency may be referred to by in nix code: its `pname`, the name part of its drv name
+/// and the last component of its attribute paths, e.g. `libXv` of `xorg.libXv`.
+pub fn dependency_names(drv_path: &str, pname: Option<&str>, attrs: &[String]) -> Vec<String> {
+    let name = drv_name(drv_path);
+    let mut names: Vec<String> = pname.into_iter().map(str::to_owned).collect();
+    names.push(parse_drv_name(&name).0.to_owned());
+    names.extend(
+        attrs
+            .iter()
+            .filter_map(|a| a.rsplit('.').next())
+            .map(str::to_owned),
+    );
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Looks up where each unused dependency of `report` is declared in the nix file its root is defined in.
+/// Positions are left as they are if that file can't be read.
+pub fn locate_declarations(report: &mut RootReport) {
+    let Some(position) = &report.position else {
+        return;
+    };
+    let nix_file = match NixFile::read(&position.file) {
+        Ok(nix_file) => nix_file,
+        Err(e) => {
+            debug!("can not read {}: {}", position.file, e);
+            return;
+        }
+    };
+    let line = Some(position.line);
+    for dep in report.unused.iter_mut() {
+        dep.position = nix_file.find_dependency(
+            line,
+            &dependency_names(&dep.drv_path, dep.pname.as_deref(), &dep.attrs),
+        );
+    }
+}
+
+impl NixFile {
+    pub fn read(path: &str) -> io::Result<Self> {
+        Ok(Self::parse(path, fs::read_to_string(path)?))
+    }
+
+    /// Parsing never fails, syntax errors only make parts of the file unsearchable.
+    pub fn parse(path: &str, source: String) -> Self {
+        let parse = Root::parse(&source);
+        if let Some(e) = parse.errors().first() {
+            debug!("{} has syntax errors: {}", path, e);
+        }
+        NixFile {
+            path: path.to_owned(),
+            root: parse.syntax(),
+            source,
+        }
+    }
+
+    /// Where a dependency known by any of `names` is declared: its element in an input list of the package at `line`,
+    /// or its argument in the `callPackage` function header if no list mentions it and the file only defines that package.
+    pub fn find_dependency(&self, line: Option<u32>, names: &[String]) -> Option<SourcePosition> {
+        let names: Vec<String> = names.iter().map(|n| normalize(n)).collect();
+        let scope = self.package_scope(line);
+        let node = self
+            .list_element(&scope, &INPUT_ATTRS, &names)
+            .or_else(|| {
+                self.is_only_package(&scope)
+                    .then(|| self.header_argument(&names))
+                    .flatten()
+            })?;
+        Some(self.position(usize::from(node.text_range().start())))
+    }
+
+    /// Byte offset of the first non-whitespace character of a 1-based line.
+    fn line_offset(&self, line: u32) -> Option<usize> {
+        let start = if line <= 1 {
+            0
+        } else {
+            self.source
+                .match_indices('\n')
+                .nth(line as usize - 2)
+                .map(|(i, _)| i + 1)?
+        };
+        let indent = self.source[start..].len() - self.source[start..].trim_start().len();
+        Some(start + indent)
+    }
+
+    /// The package defined at `line` (i.e. `meta.position`): the innermost attribute set or function application
+    /// around it that declares inputs, so files defining many packages are only searched where the package is.
+    /// The whole file if the line is unknown or not inside such a package.
+    fn package_scope(&self, line: Option<u32>) -> SyntaxNode {
+        line.and_then(|line| self.line_offset(line))
+            .filter(|offset| *offset < self.source.len())
+            .and_then(|offset| {
+                self.root
+                    .token_at_offset(TextSize::from(offset as u32))
+                    .right_biased()
+            })
+            .and_then(|token| {
+                token
+                    .parent()?
+                    .ancestors()
+                    .filter(|n| {
+                        matches!(n.kind(), SyntaxKind::NODE_ATTR_SET | SyntaxKind::NODE_APPLY)
+                    })
+                    .find(|n| input_bindings(n).next().is_some())
+            })
+            .unwrap_or_else(|| self.root.clone())
+    }
+
+    /// Whether `scope` declares every input of the file, i.e. the `callPackage` header belongs to its package alone.
+    fn is_only_package(&self, scope: &SyntaxNode) -> bool {
+        input_bindings(&self.root).all(|(_, binding)| contains(scope, &binding))
+    }
+
+    /// The smallest element of an input list in `scope` mentioning one of `names`,
+    /// so `ps.requests` wins over `python3.withPackages (ps: [ ps.requests ])`.
+    fn list_element(
+        &self,
+        scope: &SyntaxNode,
+        attrs: &[&str],
+        names: &[String],
+    ) -> Option<SyntaxNode> {
+        let bindings: Vec<(String, SyntaxNode)> = input_bindings(scope).collect();
+        attrs.iter().find_map(|attr| {
+            bindings
+                .iter()
+                .filter(|(name, _)| name == attr)
+                .flat_map(|(_, binding)| binding.descendants())
+                .filter(|n| n.kind() == SyntaxKind::NODE_LIST)
+                .flat_map(|list| list.children())
+                .filter(|element| mentions(element, names))
+                .min_by_key(|element| element.text_range().len())
+        })
+    }
+
+    /// The pattern `{ ... }` of the function the file evaluates to, i.e. the arguments `callPackage` fills in.
+    fn header(&self) -> Option<SyntaxNode> {
+        let lambda = self.root.first_child()?;
+        if lambda.kind() != SyntaxKind::NODE_LAMBDA {
+            return None;
+        }
+        lambda
+            .first_child()
+            .filter(|n| n.kind() == SyntaxKind::NODE_PATTERN)
+    }
+
+    /// The argument of the `callPackage` header named like one of `names`.
+    fn header_argument(&self, names: &[String]) -> Option<SyntaxNode> {
+        self.header()?
+            .children()
+            .filter(|n| n.kind() == SyntaxKind::NODE_PAT_ENTRY)
+            .find(|entry| {
+                entry
+                    .children()
+                    .next()
+                    .is_some_and(|ident| names.contains(&normalize(&ident.text().to_string())))
+            })
+    }
+
+    /// Converts a byte offset to a 1-based line and column, columns count characters.
+    fn position(&self, offset: usize) -> SourcePosition {
+        let before = &self.source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        SourcePosition {
+            file: self.path.clone(),
+            line: before.matches('\n').count() as u32 + 1,
+            column: Some(before[line_start..].chars().count() as u32 + 1),
+        }
+    }
+
+    /// Removes the `buildInputs` elements of the package at `line` that declare `deps`, each known by any of its names.
+    pub fn remove_build_inputs(&self, line: Option<u32>, deps: &[Vec<String>]) -> Removal {
+        let scope = self.package_scope(line);
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut referenced: Vec<String> = Vec::new();
+        let mut missing = Vec::new();
+        for (i, names) in deps.iter().enumerate() {
+            let names: Vec<String> = names.iter().map(|n| normalize(n)).collect();
+            match self.list_element(&scope, &["buildInputs"], &names) {
+                Some(element) => {
+                    ranges.push(self.removal_range(node_range(&element), false));
+                    referenced.extend(references(&element));
+                }
+                None => missing.push(i),
+            }
+        }
+        // removed back to front so earlier ranges stay valid, overlapping ones at once
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        let mut source = self.source.clone();
+        for range in merged.into_iter().rev() {
+            source.replace_range(range, "");
+        }
+        referenced.sort();
+        referenced.dedup();
+        Removal {
+            source,
+            references: referenced,
+            missing,
+            only_package: self.is_only_package(&scope),
+        }
+    }
+
+    /// The source with the argument `name` removed from the `callPackage` header, if nothing refers to it anymore.
+    /// Headers bound to a name like `args@{ ... }` are kept if that name is used, it may pass the argument on.
+    pub fn remove_unused_argument(&self, name: &str) -> Option<String> {
+        let header = self.header()?;
+        let entry = header.children().find(|n| {
+            n.kind() == SyntaxKind::NODE_PAT_ENTRY
+                && n.children()
+                    .next()
+                    .is_some_and(|ident| ident.text() == name)
+        })?;
+        let bound: Vec<String> = header
+            .children()
+            .filter(|n| n.kind() == SyntaxKind::NODE_PAT_BIND)
+            .flat_map(|bind| bind.children())
+            .map(|ident| ident.text().to_string())
+            .collect();
+        if references(&self.root).any(|r| r == name || bound.contains(&r)) {
+            return None;
+        }
+        let comma = |element: Option<NodeOrToken<SyntaxNode, _>>, forward: bool| {
+            let mut element = element;
+            let mut newline = false;
+            while let Some(e) = element {
+                match e.kind() {
+                    SyntaxKind::TOKEN_COMMA => return Some((e.text_range(), newline)),
+                    SyntaxKind::TOKEN_WHITESPACE | SyntaxKind::TOKEN_COMMENT => {
+                        newline |= e.to_string().contains('\n');
+                        element = if forward {
+                            e.next_sibling_or_token()
+                        } else {
+                            e.prev_sibling_or_token()
+                        };
+                    }
+                    _ => return None,
+                }
+            }
+            None
+        };
+        let mut range = node_range(&entry);
+        let mut last = false;
+        // a trailing comma on the same line goes with the argument, `, arg` lines of comma-first headers take the comma before
+        match (
+            comma(entry.next_sibling_or_token(), true),
+            comma(entry.prev_sibling_or_token(), false),
+        ) {
+            (Some((next, false)), _) | (Some((next, true)), None) => {
+                range.end = usize::from(next.end())
+            }
+            (_, Some((prev, _))) => {
+                range.start = usize::from(prev.start());
+                last = true;
+            }
+            (None, None) => {}
+        }
+        Some(self.without(self.removal_range(range, last)))
+    }
+
+    /// Widens `range` to whole lines if nothing but whitespace and a trailing comment shares them,
+    /// otherwise to the whitespace that separates it from what follows on the same line, or precedes it.
+    /// The whitespace before is taken for the `last` element, so `{ lib, bar }` keeps the space before `}`.
+    fn removal_range(&self, range: Range<usize>, last: bool) -> Range<usize> {
+        let line_start = self.source[..range.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[range.end..]
+            .find('\n')
+            .map_or(self.source.len(), |i| range.end + i);
+        let before = &self.source[line_start..range.start];
+        let after = self.source[range.end..line_end].trim_start();
+        if before.trim().is_empty() && (after.is_empty() || after.starts_with('#')) {
+            return line_start..(line_end + 1).min(self.source.len());
+        }
+        let following = self.source[range.end..line_end].len() - after.len();
+        if following > 0 && !after.is_empty() && !last {
+            range.start..range.end + following
+        } else {
+            range.start - (before.len() - before.trim_end().len())..range.end
+        }
+    }
+
+    fn without(&self, range: Range<usize>) -> String {
+        let mut source = self.source.clone();
+        source.replace_range(range, "");
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn find(source: &str, line: u32, dep: &[&str]) -> Option<(u32, u32)> {
+        NixFile::parse("default.nix", source.to_owned())
+            .find_dependency(Some(line), &names(dep))
+            .map(|p| (p.line, p.column.unwrap()))
+    }
+
+    const TWO_PACKAGES: &str = r#"{ buildPerlPackage, fetchurl, zlib }:
+
+{
+  Foo = buildPerlPackage {
+    pname = "Foo";
+    buildInputs = [ TestMore ];
+  };
+
+  Bar = buildPerlPackage {
+    pname = "Bar";
+    buildInputs = [ zlib ];
+  };
+}
+"#;
+
+    #[test]
+    fn only_the_package_at_the_position_is_searched() {
+        assert_eq!(find(TWO_PACKAGES, 5, &["TestMore"]), Some((6, 21)));
+        assert_eq!(find(TWO_PACKAGES, 10, &["TestMore"]), None);
+        assert_eq!(find(TWO_PACKAGES, 10, &["zlib"]), Some((11, 21)));
+    }
+
+    #[test]
+    fn header_is_not_used_for_files_with_many_packages() {
+        assert_eq!(find(TWO_PACKAGES, 5, &["zlib"]), None);
+        assert_eq!(find(TWO_PACKAGES, 5, &["fetchurl"]), None);
+    }
+
+    #[test]
+    fn with_lists() {
+        let source = r#"{ stdenv, xorg }:
+
+stdenv.mkDerivation {
+  pname = "foo";
+  buildInputs = with xorg; [ libX11 libXv ];
+}
+"#;
+        assert_eq!(find(source, 4, &["libXv"]), Some((5, 37)));
+    }
+
+    #[test]
+    fn optional_lists() {
+        let source = r#"{ lib, stdenv, zlib, libiconv }:
+
+stdenv.mkDerivation {
+  pname = "foo";
+  buildInputs = [ zlib ] ++ lib.optionals stdenv.hostPlatform.isDarwin [ libiconv ];
+}
+"#;
+        assert_eq!(find(source, 4, &["libiconv"]), Some((5, 74)));
+    }
+
+    #[test]
+    fn by_name_package() {
+        let source = r#"{
+  lib,
+  python3Packages,
+  libXv,
+}:
+
+python3Packages.buildPythonApplication {
+  pname = "foo";
+  dependencies = [ python3Packages.requests ];
+
+  meta = {
+    description = "Foo";
+    license = lib.licenses.mit;
+  };
+}
+"#;
+        // `meta.position` points at the description, inside `meta`
+        assert_eq!(find(source, 12, &["libXv"]), Some((4, 3)));
+        assert_eq!(find(source, 12, &["requests"]), None);
+    }
+
+    #[test]
+    fn nested_list_elements() {
+        let source = r#"{ stdenv, python3 }:
+
+stdenv.mkDerivation {
+  pname = "foo";
+  buildInputs = [ (python3.withPackages (ps: [ ps.requests ])) ];
+}
+"#;
+        assert_eq!(find(source, 4, &["requests"]), Some((5, 48)));
+    }
+
+    fn without_argument(source: &str, name: &str) -> Option<String> {
+        NixFile::parse("default.nix", source.to_owned()).remove_unused_argument(name)
+    }
+
+    #[test]
+    fn inline_header_arguments() {
+        let source = "{ lib, bar, baz }:\n\nlib.id baz\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib, baz }:\n\nlib.id baz\n")
+        );
+        let source = "{ lib, bar }:\n\nlib.id 1\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib }:\n\nlib.id 1\n")
+        );
+        let source = "{ bar, lib }:\n\nlib.id 1\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib }:\n\nlib.id 1\n")
+        );
+    }
+
+    #[test]
+    fn trailing_comma_headers() {
+        let source = "{\n  lib,\n  bar, # for tests\n  baz,\n}:\n\nlib.id baz\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{\n  lib,\n  baz,\n}:\n\nlib.id baz\n")
+        );
+        let source = "{\n  lib,\n  bar\n}:\n\nlib.id 1\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{\n  lib\n}:\n\nlib.id 1\n")
+        );
+    }
+
+    #[test]
+    fn comma_first_headers() {
+        let source = "{ lib\n, bar\n, baz\n}:\n\nlib.id baz\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib\n, baz\n}:\n\nlib.id baz\n")
+        );
+        let source = "{ lib\n, baz\n, bar\n}:\n\nlib.id baz\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib\n, baz\n}:\n\nlib.id baz\n")
+        );
+    }
+
+    #[test]
+    fn used_arguments_are_kept() {
+        assert_eq!(without_argument("{ lib, bar }:\n\nbar\n", "bar"), None);
+        let source = "args@{ lib, bar, ... }:\n\nlib.id args\n";
+        assert_eq!(without_argument(source, "bar"), None);
+        let source = "{ lib, bar, ... }@args:\n\nlib.id 1\n";
+        assert_eq!(
+            without_argument(source, "bar").as_deref(),
+            Some("{ lib, ... }@args:\n\nlib.id 1\n")
+        );
+    }
+
+    fn remove(source: &str, line: u32, deps: &[&[&str]]) -> Removal {
+        let deps: Vec<Vec<String>> = deps.iter().map(|d| names(d)).collect();
+        NixFile::parse("default.nix", source.to_owned()).remove_build_inputs(Some(line), &deps)
+    }
+
+    #[test]
+    fn inline_lists() {
+        let source = "{ stdenv, zlib, libXv, bzip2 }:\n\nstdenv.mkDerivation {\n  pname = \"foo\";\n  buildInputs = [ zlib libXv bzip2 ];\n}\n";
+        let removal = remove(source, 4, &[&["libXv"], &["bzip2"], &["openssl"]]);
+        assert_eq!(
+            removal.source,
+            "{ stdenv, zlib, libXv, bzip2 }:\n\nstdenv.mkDerivation {\n  pname = \"foo\";\n  buildInputs = [ zlib ];\n}\n"
+        );
+        assert_eq!(removal.references, names(&["bzip2", "libXv"]));
+        assert_eq!(removal.missing, vec![2]);
+        assert!(removal.only_package);
+    }
+
+    #[test]
+    fn lists_with_comments() {
+        let source = "{ stdenv, zlib, xorg }:\n\nstdenv.mkDerivation {\n  pname = \"foo\";\n  buildInputs = [\n    zlib # compression\n    xorg.libXv # video\n  ];\n}\n";
+        let removal = remove(source, 4, &[&["libXv"]]);
+        assert_eq!(
+            removal.source,
+            "{ stdenv, zlib, xorg }:\n\nstdenv.mkDerivation {\n  pname = \"foo\";\n  buildInputs = [\n    zlib # compression\n  ];\n}\n"
+        );
+        assert_eq!(removal.references, names(&["xorg"]));
+    }
+
+    #[test]
+    fn other_packages_are_not_changed() {
+        let removal = remove(TWO_PACKAGES, 10, &[&["TestMore"]]);
+        assert_eq!(removal.source, TWO_PACKAGES);
+        assert_eq!(removal.missing, vec![0]);
+        assert!(!removal.only_package);
+    }
+}
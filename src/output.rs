@@ -0,0 +1,136 @@
+pub mod annotations;
+pub mod dot;
+pub mod html;
+pub mod json;
+pub mod jsonl;
+pub mod junit;
+pub mod pr_markdown;
+pub mod sarif;
+pub mod sbom;
+
+use clap::ValueEnum;
+use serde_json::json;
+use std::time::SystemTime;
+
+use crate::{
+    report::{self, Failure, RootReport, UnusedDep},
+    stats::Stats,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// one line per unused dependency
+    Text,
+    /// a single versioned report, see `nix-check-deps schema`
+    Json,
+    /// one JSON record per root as soon as it is scanned, and a summary record at the end
+    Jsonl,
+    /// SARIF 2.1.0 for code scanning dashboards
+    Sarif,
+    /// a ready-to-edit nixpkgs pull request description per root
+    PrMarkdown,
+    /// a single self-contained HTML page with sortable and filterable tables
+    Html,
+    /// a graphviz graph of the roots and their inputs, coloured by usage
+    Dot,
+    /// GitHub Actions annotations
+    Github,
+    /// `file:line: severity: message` lines for editors and other CI systems
+    Gnu,
+    /// JUnit XML, a test suite per root and a test case per dependency
+    Junit,
+    /// a CycloneDX SBOM per root of the dependencies found in use
+    Cyclonedx,
+    /// an SPDX SBOM per root of the dependencies found in use
+    Spdx,
+}
+
+/// Facts about the run itself, as opposed to its findings.
+pub struct RunInfo {
+    pub started_at: SystemTime,
+    /// the run was stopped by SIGINT/SIGTERM before all roots were scanned
+    pub interrupted: bool,
+}
+
+/// Prints everything not already printed while scanning, see [`jsonl::print_root`].
+pub fn print_report(
+    reports: &[RootReport],
+    failures: &[Failure],
+    format: Format,
+    stats: bool,
+    run: &RunInfo,
+) {
+    if stats {
+        let stats = Stats::from_reports(reports);
+        if format == Format::Text {
+            print!("{}", stats);
+        } else {
+            println!("{}", json!(stats));
+        }
+        return;
+    }
+    match format {
+        Format::Text => print_text(reports),
+        Format::Json => {
+            let report = json::JsonReport::new(reports, failures, run.started_at);
+            println!("{}", serde_json::to_string(&report).unwrap());
+        }
+        Format::Jsonl => jsonl::print_summary(reports, failures, run.interrupted, run.started_at),
+        Format::Sarif => println!("{}", sarif::sarif(reports, failures, run)),
+        Format::PrMarkdown => print!("{}", pr_markdown::pr_markdown(reports)),
+        Format::Dot => print!("{}", dot::dot(reports)),
+        Format::Github => print!("{}", annotations::github(reports, failures)),
+        Format::Gnu => print!("{}", annotations::gnu(reports, failures)),
+        Format::Junit => print!("{}", junit::junit(reports, failures)),
+        // one document per line, so several roots can be exported at once
+        Format::Cyclonedx => {
+            for report in reports {
+                println!("{}", sbom::cyclonedx(report, run));
+            }
+        }
+        Format::Spdx => {
+            for report in reports {
+                println!("{}", sbom::spdx(report, run));
+            }
+        }
+        Format::Html => {
+            let report = json::JsonReport::new(reports, failures, run.started_at);
+            print!("{}", html::html(&report));
+        }
+    }
+}
+
+fn print_text(reports: &[RootReport]) {
+    let mut findings: Vec<(&RootReport, &UnusedDep)> = reports
+        .iter()
+        .flat_map(|r| r.unused.iter().map(move |d| (r, d)))
+        .collect();
+    // stable sort, so findings without sizes keep their order
+    findings.sort_by_key(|(_, d)| std::cmp::Reverse(d.savings()));
+    for (report, dep) in findings {
+        let mut line = format!(
+            "{} has unused dependency: {}",
+            report.display_name(),
+            dep.drv_path
+        );
+        if !dep.attrs.is_empty() {
+            line.push_str(&format!(" ({})", dep.attrs.join(", ")));
+        }
+        if let Some(position) = &dep.position {
+            line.push_str(&format!(" (declared at {})", position));
+        }
+        if let Some(bytes) = dep.closure_bytes {
+            line.push_str(&format!(" (saves {})", report::format_bytes(bytes)));
+        }
+        if !dep.advisories.is_empty() {
+            line.push_str(&format!(
+                " (removes {})",
+                report::advisory_ids(&dep.advisories)
+            ));
+        }
+        if let Some(known) = &dep.known_runtime {
+            line.push_str(&format!(" ({})", known));
+        }
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt::Write;
+
+use crate::report::{self, Failure, FindingClass, RootReport, SourcePosition, UnusedDep};
+
+fn message(report: &RootReport, dep: &UnusedDep) -> String {
+    let mut message = format!(
+        "unused dependency {} of {}",
+        dep.name(),
+        report.display_name()
+    );
+    if !dep.advisories.is_empty() {
+        message.push_str(&format!(
+            ", removing it removes {}",
+            report::advisory_ids(&dep.advisories)
+        ));
+    }
+    if let Some(known) = &dep.known_runtime {
+        message.push_str(&format!(" ({})", known));
+    }
+    message
+}
+
+// https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+fn github_command(
+    level: &str,
+    position: Option<&SourcePosition>,
+    title: &str,
+    message: &str,
+) -> String {
+    let mut properties = Vec::new();
+    if let Some(position) = position {
+        properties.push(format!(
+            "file={}",
+            escape_property(&position.workspace_file())
+        ));
+        properties.push(format!("line={}", position.line));
+        if let Some(column) = position.column {
+            properties.push(format!("col={}", column));
+        }
+    }
+    properties.push(format!("title={}", escape_property(title)));
+    format!(
+        "::{} {}::{}",
+        level,
+        properties.join(","),
+        escape_data(message)
+    )
+}
+
+/// GitHub Actions workflow commands, shown inline on the declaration of each dependency or the nix file of each root if known.
+pub fn github(reports: &[RootReport], failures: &[Failure]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        for dep in &report.unused {
+            let level = match dep.class() {
+                FindingClass::Unused => "warning",
+                FindingClass::KnownRuntime => "notice",
+            };
+            let command = github_command(
+                level,
+                report.position_of(dep),
+                dep.class().rule_id(),
+                &message(report, dep),
+            );
+            writeln!(out, "{}", command).unwrap();
+        }
+    }
+    let all_failures = failures.iter().map(|f| (None, f)).chain(
+        reports
+            .iter()
+            .flat_map(|r| r.failures.iter().map(move |f| (Some(r), f))),
+    );
+    for (report, failure) in all_failures {
+        let title = format!("nix-check-deps: {} failure", failure.kind);
+        writeln!(
+            out,
+            "{}",
+            github_command(
+                "error",
+                report.and_then(|r| r.position.as_ref()),
+                &title,
+                &failure.message
+            )
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn single_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Where a finding is reported in the GNU format, the drv file of the root if no nix file is known.
+fn gnu_location(report: &RootReport, position: Option<&SourcePosition>) -> String {
+    match position {
+        Some(position) => {
+            let mut location = format!("{}:{}", position.workspace_file(), position.line);
+            if let Some(column) = position.column {
+                location.push_str(&format!(":{}", column));
+            }
+            location
+        }
+        None => report.root.clone(),
+    }
+}
+
+/// `file:line: severity: message` lines, as understood by most editors and CI systems.
+pub fn gnu(reports: &[RootReport], failures: &[Failure]) -> String {
+    let mut out = String::new();
+    for report in reports {
+        for dep in &report.unused {
+            let severity = match dep.class() {
+                FindingClass::Unused => "warning",
+                FindingClass::KnownRuntime => "note",
+            };
+            writeln!(
+                out,
+                "{}: {}: {}",
+                gnu_location(report, report.position_of(dep)),
+                severity,
+                message(report, dep)
+            )
+            .unwrap();
+        }
+        for failure in &report.failures {
+            writeln!(
+                out,
+                "{}: error: {}",
+                gnu_location(report, report.position.as_ref()),
+                single_line(&failure.message)
+            )
+            .unwrap();
+        }
+    }
+    for failure in failures {
+        writeln!(
+            out,
+            "nix-check-deps: error: {}",
+            single_line(&failure.message)
+        )
+        .unwrap();
+    }
+    out
+}
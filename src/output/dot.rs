@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    derivation::drv_name,
+    report::{FilterReason, RootReport, Scanner},
+};
+
+/// Status of an edge from a root to one of its inputs.
+#[derive(Debug, Clone, Copy)]
+enum Status {
+    Used(Scanner),
+    Unused,
+    Filtered(FilterReason),
+}
+
+impl Status {
+    fn label(&self) -> String {
+        match self {
+            Status::Used(scanner) => format!("used: {}", scanner),
+            Status::Unused => "unused".to_owned(),
+            Status::Filtered(FilterReason::NotBuildInput) => "not a build input".to_owned(),
+            Status::Filtered(FilterReason::Propagated) => "propagated".to_owned(),
+            Status::Filtered(FilterReason::CheckInput) => "check input".to_owned(),
+            Status::Filtered(FilterReason::Allowlisted) => "allowlisted".to_owned(),
+        }
+    }
+
+    fn attrs(&self) -> &'static str {
+        match self {
+            Status::Used(Scanner::References) => r#"color="forestgreen""#,
+            Status::Used(Scanner::Headers) => r#"color="royalblue""#,
+            Status::Used(Scanner::SharedObjects) => r#"color="purple""#,
+            Status::Used(Scanner::Shebangs) => r#"color="teal""#,
+            Status::Used(Scanner::Pyproject) => r#"color="olivedrab""#,
+            Status::Unused => r#"color="red", penwidth=2"#,
+            Status::Filtered(FilterReason::NotBuildInput) => r#"color="gray80""#,
+            Status::Filtered(FilterReason::Propagated) => r#"color="gray50", style="dashed""#,
+            Status::Filtered(FilterReason::CheckInput) => r#"color="gray50", style="dotted""#,
+            Status::Filtered(FilterReason::Allowlisted) => r#"color="orange", style="dashed""#,
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A graphviz graph of every root and its inputs, edges are coloured by whether and how the input is used.
+pub fn dot(reports: &[RootReport]) -> String {
+    let mut out = String::new();
+    let mut nodes: BTreeSet<&str> = BTreeSet::new();
+    let mut edges: Vec<(&str, &str, Status)> = Vec::new();
+
+    for report in reports {
+        nodes.insert(&report.root);
+        let used = report
+            .used
+            .iter()
+            .map(|d| (&d.drv_path, Status::Used(d.scanner)));
+        let unused = report.unused.iter().map(|d| (&d.drv_path, Status::Unused));
+        let filtered = report
+            .filtered
+            .iter()
+            .map(|d| (&d.drv_path, Status::Filtered(d.reason)));
+        for (dep, status) in used.chain(unused).chain(filtered) {
+            nodes.insert(dep);
+            edges.push((&report.root, dep, status));
+        }
+    }
+    let roots: BTreeMap<&str, Option<&String>> = reports
+        .iter()
+        .map(|r| (r.root.as_str(), r.attr.as_ref()))
+        .collect();
+    // inputs are labelled by their attribute path if known, see `--attr-index`
+    let dep_attrs: BTreeMap<&str, &String> = reports
+        .iter()
+        .flat_map(|r| {
+            let used = r.used.iter().map(|d| (&d.drv_path, d.attrs.first()));
+            let unused = r.unused.iter().map(|d| (&d.drv_path, d.attrs.first()));
+            used.chain(unused)
+        })
+        .filter_map(|(dep, attr)| Some((dep.as_str(), attr?)))
+        .collect();
+
+    writeln!(out, "digraph nix_check_deps {{").unwrap();
+    writeln!(out, "  rankdir=LR;").unwrap();
+    writeln!(out, "  node [shape=box, fontname=\"monospace\"];").unwrap();
+    for node in &nodes {
+        match roots.get(node) {
+            Some(attr) => writeln!(
+                out,
+                "  {} [label={}, style=\"bold\"];",
+                quote(node),
+                quote(&attr.cloned().unwrap_or_else(|| drv_name(node)))
+            ),
+            None => writeln!(
+                out,
+                "  {} [label={}];",
+                quote(node),
+                quote(
+                    &dep_attrs
+                        .get(node)
+                        .map_or_else(|| drv_name(node), |a| a.to_string())
+                )
+            ),
+        }
+        .unwrap();
+    }
+    for (root, dep, status) in edges {
+        writeln!(
+            out,
+            "  {} -> {} [label={}, {}];",
+            quote(root),
+            quote(dep),
+            quote(&status.label()),
+            status.attrs()
+        )
+        .unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
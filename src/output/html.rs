@@ -0,0 +1,10 @@
+use super::json::JsonReport;
+
+const TEMPLATE: &str = include_str!("report.html");
+
+/// A self-contained HTML page rendering `report`, usable offline.
+pub fn html(report: &JsonReport) -> String {
+    // the JSON is embedded in a script tag, which must not be closed by strings in it
+    let data = serde_json::to_string(report).unwrap().replace("</", "<\\/");
+    TEMPLATE.replace("{{REPORT}}", &data)
+}
@@ -0,0 +1,189 @@
+use humantime::format_rfc3339_seconds;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::{collections::BTreeMap, time::SystemTime};
+
+use crate::{
+    derivation,
+    report::{
+        Advisory, Confidence, Failure, FailureKind, FindingClass, InputCategory, KnownRuntime,
+        RootReport, Scanner, ScannerRun, SourcePosition,
+    },
+};
+
+/// Bumped on every change that can break consumers of the report, i.e. removed or renamed fields.
+/// New optional fields don't bump it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Findings of one run of nix-check-deps.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct JsonReport {
+    pub schema_version: u32,
+    pub metadata: RunMetadata,
+    /// every root that was scanned, sorted by drv path
+    pub roots: Vec<JsonRoot>,
+    /// every unused dependency, sorted by root and dependency drv path
+    pub findings: Vec<Finding>,
+    /// problems that made the run incomplete, sorted by root
+    pub failures: Vec<JsonFailure>,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct RunMetadata {
+    pub tool_version: String,
+    /// version of the nix used for evaluation and building, if it could be queried
+    pub nix_version: Option<String>,
+    /// command line arguments, without the program name
+    pub arguments: Vec<String>,
+    /// RFC 3339, UTC
+    pub started_at: String,
+    /// RFC 3339, UTC
+    pub finished_at: String,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct JsonRoot {
+    pub root: String,
+    pub attr: Option<String>,
+    pub pname: Option<String>,
+    pub position: Option<SourcePosition>,
+    pub unused: usize,
+    pub used: usize,
+    pub scanners: Vec<ScannerRun>,
+}
+
+/// A dependency of a root that was not found to be in use.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Finding {
+    /// drv path of the root
+    pub root: String,
+    pub root_attr: Option<String>,
+    pub root_pname: Option<String>,
+    pub class: FindingClass,
+    pub confidence: Confidence,
+    /// drv path of the dependency
+    pub dep: String,
+    pub pname: Option<String>,
+    /// attribute paths of the dependency, shortest first, see `--attr-index`
+    pub attrs: Vec<String>,
+    /// output name to store path of the dependency
+    pub outputs: BTreeMap<String, String>,
+    pub input_category: InputCategory,
+    /// where the dependency is declared in the nix file of the root, if found
+    pub position: Option<SourcePosition>,
+    /// scanners that checked the root, none of them found the dependency in use;
+    /// source scanners are left out if the source could not be unpacked
+    pub scanners_run: Vec<Scanner>,
+    pub evidence: Vec<Evidence>,
+    /// bytes that disappear from the closure if the dependency is removed, if computed
+    pub closure_bytes: Option<u64>,
+    /// known vulnerabilities removed from the closure together with the dependency, see `--advisories`
+    pub advisories: Vec<Advisory>,
+    pub known_runtime: Option<KnownRuntime>,
+}
+
+/// What a scanner checked without finding the dependency in use.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Evidence {
+    pub scanner: Scanner,
+    pub detail: String,
+}
+
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct JsonFailure {
+    /// drv path of the root, unset if the target could not be evaluated to a root at all
+    pub root: Option<String>,
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+/// All findings of `reports`, sorted by root and dependency.
+pub fn findings(reports: &[RootReport]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = reports
+        .iter()
+        .flat_map(|r| {
+            let scanners_run: Vec<Scanner> = r.effective_scanners().map(|s| s.scanner).collect();
+            r.unused.iter().map(move |d| Finding {
+                root: r.root.clone(),
+                root_attr: r.attr.clone(),
+                root_pname: r.pname.clone(),
+                class: d.class(),
+                confidence: r.confidence(d),
+                dep: d.drv_path.clone(),
+                pname: d.pname.clone(),
+                attrs: d.attrs.clone(),
+                outputs: d.outputs.clone(),
+                input_category: d.input_category,
+                position: d.position.clone(),
+                evidence: scanners_run
+                    .iter()
+                    .map(|s| Evidence {
+                        scanner: *s,
+                        detail: s.absence().to_owned(),
+                    })
+                    .collect(),
+                scanners_run: scanners_run.clone(),
+                closure_bytes: d.closure_bytes,
+                advisories: d.advisories.clone(),
+                known_runtime: d.known_runtime.clone(),
+            })
+        })
+        .collect();
+    findings.sort_by(|a, b| a.root.cmp(&b.root).then_with(|| a.dep.cmp(&b.dep)));
+    findings
+}
+
+impl JsonReport {
+    pub fn new(reports: &[RootReport], failures: &[Failure], started_at: SystemTime) -> Self {
+        let mut roots: Vec<JsonRoot> = reports
+            .iter()
+            .map(|r| JsonRoot {
+                root: r.root.clone(),
+                attr: r.attr.clone(),
+                pname: r.pname.clone(),
+                position: r.position.clone(),
+                unused: r.unused.len(),
+                used: r.used.len(),
+                scanners: r.scanners.clone(),
+            })
+            .collect();
+        roots.sort_by(|a, b| a.root.cmp(&b.root));
+
+        let mut json_failures: Vec<JsonFailure> = failures
+            .iter()
+            .map(|f| JsonFailure {
+                root: None,
+                kind: f.kind,
+                message: f.message.clone(),
+            })
+            .chain(reports.iter().flat_map(|r| {
+                r.failures.iter().map(|f| JsonFailure {
+                    root: Some(r.root.clone()),
+                    kind: f.kind,
+                    message: f.message.clone(),
+                })
+            }))
+            .collect();
+        // stable sort, so failures of one root keep the order they happened in
+        json_failures.sort_by(|a, b| a.root.cmp(&b.root));
+
+        JsonReport {
+            schema_version: SCHEMA_VERSION,
+            metadata: RunMetadata {
+                tool_version: env!("CARGO_PKG_VERSION").to_owned(),
+                nix_version: derivation::nix_version(),
+                arguments: std::env::args().skip(1).collect(),
+                started_at: format_rfc3339_seconds(started_at).to_string(),
+                finished_at: format_rfc3339_seconds(SystemTime::now()).to_string(),
+            },
+            roots,
+            findings: findings(reports),
+            failures: json_failures,
+        }
+    }
+}
+
+/// JSON Schema of the `--json` report.
+pub fn schema() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(JsonReport)).unwrap()
+}
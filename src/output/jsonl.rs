@@ -0,0 +1,88 @@
+use humantime::format_rfc3339_seconds;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use super::json::{self, Finding, JsonFailure, SCHEMA_VERSION};
+use crate::report::{Failure, RootReport, ScannerRun};
+
+/// One line of `--format jsonl` output, tagged by `type`.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Record {
+    /// emitted as soon as a root is scanned
+    Root {
+        root: String,
+        attr: Option<String>,
+        pname: Option<String>,
+        scanners: Vec<ScannerRun>,
+        findings: Vec<Finding>,
+        failures: Vec<JsonFailure>,
+    },
+    /// a target that could not be evaluated to a root
+    Failure(JsonFailure),
+    /// emitted once at the end of the run, also if it was interrupted
+    Summary {
+        schema_version: u32,
+        roots: usize,
+        unused: usize,
+        failures: usize,
+        interrupted: bool,
+        started_at: String,
+        finished_at: String,
+    },
+}
+
+fn print_record(record: &Record) {
+    let mut stdout = io::stdout().lock();
+    // flush every line, so the output can be followed while the run goes on
+    writeln!(stdout, "{}", serde_json::to_string(record).unwrap())
+        .and_then(|_| stdout.flush())
+        .ok();
+}
+
+pub fn print_root(report: &RootReport) {
+    print_record(&Record::Root {
+        root: report.root.clone(),
+        attr: report.attr.clone(),
+        pname: report.pname.clone(),
+        scanners: report.scanners.clone(),
+        findings: json::findings(std::slice::from_ref(report)),
+        failures: report
+            .failures
+            .iter()
+            .map(|f| JsonFailure {
+                root: Some(report.root.clone()),
+                kind: f.kind,
+                message: f.message.clone(),
+            })
+            .collect(),
+    });
+}
+
+/// Prints the failures of targets that never became a root, then the summary of the run.
+pub fn print_summary(
+    reports: &[RootReport],
+    failures: &[Failure],
+    interrupted: bool,
+    started_at: SystemTime,
+) {
+    for failure in failures {
+        print_record(&Record::Failure(JsonFailure {
+            root: None,
+            kind: failure.kind,
+            message: failure.message.clone(),
+        }));
+    }
+    print_record(&Record::Summary {
+        schema_version: SCHEMA_VERSION,
+        roots: reports.len(),
+        unused: reports.iter().map(|r| r.unused.len()).sum(),
+        failures: failures.len() + reports.iter().map(|r| r.failures.len()).sum::<usize>(),
+        interrupted,
+        started_at: format_rfc3339_seconds(started_at).to_string(),
+        finished_at: format_rfc3339_seconds(SystemTime::now()).to_string(),
+    });
+}
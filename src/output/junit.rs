@@ -0,0 +1,214 @@
+use std::fmt::Write;
+
+use crate::{
+    derivation::drv_name,
+    report::{Failure, FilterReason, RootReport},
+};
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in XML 1.0 at all
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+struct TestCase {
+    name: String,
+    /// `(element, type, message, body)` of a failed, erroneous or skipped test case
+    outcome: Option<(&'static str, String, String, String)>,
+}
+
+struct TestSuite {
+    name: String,
+    root: Option<String>,
+    seconds: f64,
+    cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    fn count(&self, element: &str) -> usize {
+        self.cases
+            .iter()
+            .filter(|c| c.outcome.as_ref().is_some_and(|o| o.0 == element))
+            .count()
+    }
+}
+
+fn suite(report: &RootReport) -> TestSuite {
+    let mut cases: Vec<TestCase> = Vec::new();
+
+    for used in &report.used {
+        cases.push(TestCase {
+            name: drv_name(&used.drv_path),
+            outcome: None,
+        });
+    }
+    for dep in &report.unused {
+        let evidence: Vec<String> = report
+            .effective_scanners()
+            .map(|r| format!("{}: {}", r.scanner, r.scanner.absence()))
+            .chain(
+                dep.advisories
+                    .iter()
+                    .map(|a| format!("removes advisory {} of {}", a, a.package)),
+            )
+            .chain(dep.known_runtime.iter().map(|k| k.to_string()))
+            .collect();
+        cases.push(TestCase {
+            name: drv_name(&dep.drv_path),
+            outcome: Some((
+                "failure",
+                dep.class().rule_id().to_owned(),
+                format!(
+                    "unused dependency {}{} (confidence: {})",
+                    dep.drv_path,
+                    if dep.attrs.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" ({})", dep.attrs.join(", "))
+                    },
+                    report.confidence(dep)
+                ),
+                evidence.join("\n"),
+            )),
+        });
+    }
+    for filtered in report
+        .filtered
+        .iter()
+        .filter(|f| f.reason != FilterReason::NotBuildInput)
+    {
+        let message = match &filtered.detail {
+            Some(detail) => format!("{}: {}", filtered.reason, detail),
+            None => filtered.reason.to_string(),
+        };
+        cases.push(TestCase {
+            name: drv_name(&filtered.drv_path),
+            outcome: Some(("skipped", String::new(), message, String::new())),
+        });
+    }
+    for failure in &report.failures {
+        cases.push(TestCase {
+            name: failure.kind.to_string(),
+            outcome: Some((
+                "error",
+                failure.kind.to_string(),
+                failure
+                    .message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned(),
+                failure.message.clone(),
+            )),
+        });
+    }
+
+    TestSuite {
+        name: report.name(),
+        root: Some(report.root.clone()),
+        seconds: report.scanners.iter().fold(0.0, |s, r| s + r.seconds),
+        cases,
+    }
+}
+
+/// JUnit XML with a test suite per root and a test case per checked dependency, which fails if the dependency is unused.
+/// Incomplete scans are errors, unchecked dependencies are skipped.
+pub fn junit(reports: &[RootReport], failures: &[Failure]) -> String {
+    let mut suites: Vec<TestSuite> = reports.iter().map(suite).collect();
+    if !failures.is_empty() {
+        suites.push(TestSuite {
+            name: "evaluation".to_owned(),
+            root: None,
+            seconds: 0.0,
+            cases: failures
+                .iter()
+                .map(|f| TestCase {
+                    name: f.kind.to_string(),
+                    outcome: Some((
+                        "error",
+                        f.kind.to_string(),
+                        f.message.clone(),
+                        String::new(),
+                    )),
+                })
+                .collect(),
+        });
+    }
+
+    let total = |element: &str| suites.iter().map(|s| s.count(element)).sum::<usize>();
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<testsuites name="nix-check-deps" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+        suites.iter().map(|s| s.cases.len()).sum::<usize>(),
+        total("failure"),
+        total("error"),
+        total("skipped"),
+        suites.iter().fold(0.0, |s, suite| s + suite.seconds)
+    )
+    .unwrap();
+    for suite in &suites {
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+            escape(&suite.name),
+            suite.cases.len(),
+            suite.count("failure"),
+            suite.count("error"),
+            suite.count("skipped"),
+            suite.seconds
+        )
+        .unwrap();
+        if let Some(root) = &suite.root {
+            writeln!(
+                out,
+                r#"    <properties><property name="drv" value="{}"/></properties>"#,
+                escape(root)
+            )
+            .unwrap();
+        }
+        for case in &suite.cases {
+            let open = format!(
+                r#"    <testcase classname="{}" name="{}""#,
+                escape(&suite.name),
+                escape(&case.name)
+            );
+            match &case.outcome {
+                None => writeln!(out, "{}/>", open).unwrap(),
+                Some(("skipped", _, message, _)) => writeln!(
+                    out,
+                    r#"{}><skipped message="{}"/></testcase>"#,
+                    open,
+                    escape(message)
+                )
+                .unwrap(),
+                Some((element, kind, message, body)) => writeln!(
+                    out,
+                    r#"{}><{} type="{}" message="{}">{}</{}></testcase>"#,
+                    open,
+                    element,
+                    escape(kind),
+                    escape(message),
+                    escape(body),
+                    element
+                )
+                .unwrap(),
+            }
+        }
+        writeln!(out, "  </testsuite>").unwrap();
+    }
+    writeln!(out, "</testsuites>").unwrap();
+    out
+}
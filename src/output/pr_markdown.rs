@@ -0,0 +1,159 @@
+use std::fmt::Write;
+
+use crate::report::{self, FailureKind, RootReport, Scanner, UnusedDep};
+
+/// Index of the checklist step the source scanners partially cover.
+const UPSTREAM_CODE_STEP: usize = 4;
+
+/// The checklist of the README, `true` for steps nix-check-deps does itself.
+const CHECKLIST: [(bool, &str); 9] = [
+    (true, "find a potentially unused dependency with `nix-check-deps`"),
+    (false, "upstream package has a commit/PR removing the dependency"),
+    (false, "upstream package has a release that includes the commit/PR doing the remove"),
+    (false, "upstream has a changelog documenting the removal of a dependency (or feature that was the sole last user of a dependency)"),
+    (false, "upstream code does not refer to the dependency"),
+    (false, "why did nixpkgs add the dependency? (`git blame`, historical issues and PRs, old upstream build scripts)"),
+    (false, "impact assessment: what depends on the package, do all the tests still run? ([`nixpkgs-review`](https://github.com/Mic92/nixpkgs-review) only builds, it does not execute)"),
+    (false, "followed [CONTRIBUTING.md](https://github.com/NixOS/nixpkgs/blob/master/CONTRIBUTING.md)"),
+    (false, "added the `closure size` tag"),
+];
+
+/// A pull request body for removing the unused dependencies of one root.
+pub fn pr_body(report: &RootReport) -> String {
+    let mut out = String::new();
+    let name = report.name();
+    let deps: Vec<String> = report.unused.iter().map(UnusedDep::name).collect();
+
+    writeln!(out, "<!-- root: {} -->", report.root).unwrap();
+    writeln!(out, "## {}: remove unused dependencies\n", name).unwrap();
+    writeln!(
+        out,
+        "Removes {} from `{}`, `nix-check-deps` {} found no use of {}.\n",
+        deps.iter()
+            .map(|d| format!("`{}`", d))
+            .collect::<Vec<_>>()
+            .join(", "),
+        name,
+        env!("CARGO_PKG_VERSION"),
+        if deps.len() == 1 { "it" } else { "them" }
+    )
+    .unwrap();
+
+    let sizes = report.unused.iter().any(|d| d.closure_bytes.is_some());
+    if sizes {
+        writeln!(out, "| dependency | drv | closure size saved |").unwrap();
+        writeln!(out, "|---|---|---|").unwrap();
+    } else {
+        writeln!(out, "| dependency | drv |").unwrap();
+        writeln!(out, "|---|---|").unwrap();
+    }
+    for dep in &report.unused {
+        write!(out, "| `{}` | `{}` |", dep.name(), dep.drv_path).unwrap();
+        if sizes {
+            let saved = dep.closure_bytes.map(report::format_bytes);
+            write!(out, " {} |", saved.unwrap_or_default()).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    if sizes {
+        let total: u64 = report.unused.iter().flat_map(|d| d.closure_bytes).sum();
+        writeln!(
+            out,
+            "\nClosure size: up to {} smaller. Dependencies may share parts of their closure, so savings don't always add up.",
+            report::format_bytes(total)
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\n### Evidence of non-use\n").unwrap();
+    for dep in &report.unused {
+        writeln!(out, "- `{}`:", dep.name()).unwrap();
+        if let Some(position) = &dep.position {
+            writeln!(
+                out,
+                "  - declared at `{}:{}:{}`",
+                position.workspace_file(),
+                position.line,
+                position.column.unwrap_or(1)
+            )
+            .unwrap();
+        }
+        for run in report.effective_scanners() {
+            writeln!(out, "  - {}: {}", run.scanner, run.scanner.absence()).unwrap();
+        }
+        for advisory in &dep.advisories {
+            if advisory.unverified {
+                writeln!(
+                    out,
+                    "  - may remove `{}` of `{}` from the closure, the closure could not be measured",
+                    advisory.id, advisory.package
+                )
+            } else {
+                writeln!(
+                    out,
+                    "  - removes `{}` of `{}` from the closure",
+                    advisory.id, advisory.package
+                )
+            }
+            .unwrap();
+        }
+        if let Some(known) = &dep.known_runtime {
+            writeln!(
+                out,
+                "  - **known runtime-only dependency**, verify manually: {}; {}",
+                known.reason, known.verify
+            )
+            .unwrap();
+        }
+    }
+
+    let not_run: Vec<Scanner> = Scanner::ALL
+        .into_iter()
+        .filter(|s| !report.effective_scanners().any(|r| r.scanner == *s))
+        .collect();
+    if !not_run.is_empty() || !report.failures.is_empty() {
+        writeln!(out, "\n### Scanners that did not run\n").unwrap();
+        for scanner in not_run {
+            writeln!(out, "- {}", scanner).unwrap();
+        }
+        for failure in &report.failures {
+            let what = match failure.kind {
+                FailureKind::Evaluation => "evaluation failed",
+                FailureKind::Build => "build failed",
+                FailureKind::SourceExtraction => "source could not be unpacked",
+            };
+            writeln!(out, "- {}: {}", what, failure.message).unwrap();
+        }
+    }
+
+    writeln!(out, "\n### Checklist\n").unwrap();
+    let source_scanners: Vec<String> = report
+        .effective_scanners()
+        .filter(|r| r.scanner.reads_source())
+        .map(|r| r.scanner.to_string())
+        .collect();
+    let source_scanned = !source_scanners.is_empty();
+    for (i, (done, step)) in CHECKLIST.into_iter().enumerate() {
+        writeln!(out, "- [{}] {}", if done { "x" } else { " " }, step).unwrap();
+        if i == UPSTREAM_CODE_STEP && source_scanned {
+            writeln!(
+                out,
+                "    - `nix-check-deps` checked the unpacked source for {} only",
+                source_scanners.join(", ")
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "\n### Research and testing\n\n<!-- explain why it is safe to drop the dependencies, and the research and testing done -->").unwrap();
+    out
+}
+
+/// PR bodies of all roots with unused dependencies, separated by horizontal rules.
+pub fn pr_markdown(reports: &[RootReport]) -> String {
+    reports
+        .iter()
+        .filter(|r| !r.unused.is_empty())
+        .map(pr_body)
+        .collect::<Vec<_>>()
+        .join("\n---\n\n")
+}
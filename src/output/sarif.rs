@@ -0,0 +1,172 @@
+use serde_json::{json, Value};
+
+use super::RunInfo;
+use crate::{
+    baseline,
+    report::{self, Failure, FindingClass, RootReport, SourcePosition, UnusedDep},
+};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// `uriBaseId` of nix files that were copied to the store, e.g. as part of a flake
+const SOURCE_ROOT: &str = "SRCROOT";
+
+fn rule(class: FindingClass) -> Value {
+    json!({
+        "id": class.rule_id(),
+        "shortDescription": { "text": class.description() },
+        "defaultConfiguration": { "level": level(class) },
+    })
+}
+
+fn level(class: FindingClass) -> &'static str {
+    match class {
+        FindingClass::Unused => "warning",
+        FindingClass::KnownRuntime => "note",
+    }
+}
+
+fn artifact_location(position: &SourcePosition) -> Value {
+    match position.source_relative_file() {
+        Some(relative) => json!({ "uri": relative, "uriBaseId": SOURCE_ROOT }),
+        None => json!({ "uri": format!("file://{}", position.file) }),
+    }
+}
+
+/// Points at `position` in a nix file if known, the drv file of the root otherwise.
+fn location(report: &RootReport, position: Option<&SourcePosition>) -> Value {
+    match position {
+        Some(position) => {
+            let mut region = json!({ "startLine": position.line });
+            if let Some(column) = position.column {
+                region["startColumn"] = json!(column);
+            }
+            json!({
+                "physicalLocation": {
+                    "artifactLocation": artifact_location(position),
+                    "region": region,
+                },
+            })
+        }
+        None => json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": format!("file://{}", report.root) },
+            },
+        }),
+    }
+}
+
+fn result(report: &RootReport, dep: &UnusedDep) -> Value {
+    let class = dep.class();
+    let mut message = format!(
+        "{} has unused dependency {}",
+        report.display_name(),
+        dep.name()
+    );
+    if !dep.advisories.is_empty() {
+        message.push_str(&format!(
+            ", removing it removes {}",
+            report::advisory_ids(&dep.advisories)
+        ));
+    }
+    if let Some(known) = &dep.known_runtime {
+        message.push_str(&format!(" ({})", known));
+    }
+    let evidence: Vec<Value> = report
+        .effective_scanners()
+        .enumerate()
+        .map(|(id, run)| {
+            json!({
+                "id": id,
+                "message": { "text": format!("{}: {}", run.scanner, run.scanner.absence()) },
+            })
+        })
+        .collect();
+    let entry = baseline::entry(report, dep);
+    json!({
+        "ruleId": class.rule_id(),
+        "ruleIndex": FindingClass::ALL.iter().position(|c| *c == class),
+        "level": level(class),
+        "message": { "text": message },
+        "locations": [location(report, report.position_of(dep))],
+        "relatedLocations": evidence,
+        "partialFingerprints": { "rootDependency/v1": format!("{}/{}", entry.root, entry.dep) },
+        "properties": {
+            "root": report.root,
+            "dependency": dep.drv_path,
+            "attributes": dep.attrs,
+            "closureBytes": dep.closure_bytes,
+            "advisories": dep.advisories,
+        },
+    })
+}
+
+/// Where `SRCROOT` points: the store path of the source tree if every position is in the same one,
+/// left to the consumer otherwise, e.g. GitHub code scanning resolves it to the checkout.
+fn source_root(reports: &[RootReport]) -> Value {
+    let mut roots: Vec<&str> = reports
+        .iter()
+        .flat_map(|r| {
+            r.position
+                .iter()
+                .chain(r.unused.iter().flat_map(|d| &d.position))
+        })
+        .filter_map(SourcePosition::source_root)
+        .collect();
+    roots.sort();
+    roots.dedup();
+    let mut base = json!({
+        "description": { "text": "root of the source tree nix files were evaluated from, e.g. the nixpkgs checkout" },
+    });
+    if let [root] = roots[..] {
+        base["uri"] = json!(format!("file://{}", root));
+    }
+    base
+}
+
+/// A SARIF 2.1.0 log with one result per unused dependency.
+pub fn sarif(reports: &[RootReport], failures: &[Failure], run: &RunInfo) -> Value {
+    let results: Vec<Value> = reports
+        .iter()
+        .flat_map(|r| r.unused.iter().map(move |d| result(r, d)))
+        .collect();
+    let notifications: Vec<Value> = failures
+        .iter()
+        .map(|f| (None, f))
+        .chain(
+            reports
+                .iter()
+                .flat_map(|r| r.failures.iter().map(move |f| (Some(r), f))),
+        )
+        .map(|(report, f)| {
+            let mut notification = json!({
+                "level": "error",
+                "message": { "text": f.message },
+                "properties": { "kind": f.kind },
+            });
+            if let Some(report) = report {
+                notification["locations"] = json!([location(report, report.position.as_ref())]);
+            }
+            notification
+        })
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": FindingClass::ALL.iter().map(|c| rule(*c)).collect::<Vec<_>>(),
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": !run.interrupted,
+                "toolExecutionNotifications": notifications,
+            }],
+            "originalUriBaseIds": { SOURCE_ROOT: source_root(reports) },
+            "results": results,
+        }],
+    })
+}
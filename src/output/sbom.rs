@@ -0,0 +1,197 @@
+use humantime::format_rfc3339_seconds;
+use serde_json::{json, Value};
+
+use super::RunInfo;
+use crate::{
+    derivation::drv_name,
+    report::{RootReport, Scanner, UsedDep},
+};
+
+fn root_name(report: &RootReport) -> String {
+    report
+        .pname
+        .clone()
+        .unwrap_or_else(|| drv_name(&report.root))
+}
+
+fn dep_name(dep: &UsedDep) -> String {
+    dep.pname.clone().unwrap_or_else(|| drv_name(&dep.drv_path))
+}
+
+/// A CycloneDX 1.5 BOM of the root, the dependencies found to be in use.
+/// Dependencies only found in the source, i.e. by headers or pyproject, are build-only and get the `excluded` scope.
+pub fn cyclonedx(report: &RootReport, run: &RunInfo) -> Value {
+    let components: Vec<Value> = report
+        .used
+        .iter()
+        .map(|dep| {
+            let build_only = dep.scanner.build_only();
+            let mut component = json!({
+                "type": "library",
+                "bom-ref": dep.drv_path,
+                "name": dep_name(dep),
+                "scope": if build_only { "excluded" } else { "required" },
+                "properties": [
+                    { "name": "nix-check-deps:usage", "value": dep.scanner.usage() },
+                    { "name": "nix-check-deps:scanner", "value": dep.scanner },
+                    { "name": "nix-check-deps:build-only", "value": build_only.to_string() },
+                    { "name": "nix:drv_path", "value": dep.drv_path },
+                ],
+            });
+            for attr in &dep.attrs {
+                component["properties"]
+                    .as_array_mut()
+                    .unwrap()
+                    .push(json!({ "name": "nix:attr", "value": attr }));
+            }
+            if let Some(version) = &dep.version {
+                component["version"] = json!(version);
+            }
+            component
+        })
+        .collect();
+    let mut root = json!({
+        "type": "application",
+        "bom-ref": report.root,
+        "name": root_name(report),
+        "properties": [{ "name": "nix:drv_path", "value": report.root }],
+    });
+    if let Some(version) = &report.version {
+        root["version"] = json!(version);
+    }
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": format_rfc3339_seconds(run.started_at).to_string(),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            "component": root,
+        },
+        "components": components,
+        "dependencies": [{
+            "ref": report.root,
+            "dependsOn": report
+                .used
+                .iter()
+                .map(|d| &d.drv_path)
+                .collect::<Vec<_>>(),
+        }],
+    })
+}
+
+fn store_basename(drv_path: &str) -> &str {
+    drv_path.strip_prefix("/nix/store/").unwrap_or(drv_path)
+}
+
+// SPDX ids may only contain letters, digits, `.` and `-`
+fn spdx_id(drv_path: &str) -> String {
+    let id: String = store_basename(drv_path)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("SPDXRef-{}", id)
+}
+
+/// The SPDX relationship between root and a dependency used as found by `scanner`, `true` if it points from the dependency to the root.
+/// Uses only found in the source are build dependencies.
+fn spdx_relationship(scanner: Scanner) -> (&'static str, bool) {
+    match scanner {
+        Scanner::SharedObjects => ("DYNAMIC_LINK", false),
+        Scanner::Shebangs => ("RUNTIME_DEPENDENCY_OF", true),
+        Scanner::Headers | Scanner::Pyproject => ("BUILD_DEPENDENCY_OF", true),
+        Scanner::References => ("DEPENDS_ON", false),
+    }
+}
+
+fn spdx_package(drv_path: &str, name: String, version: &Option<String>, comment: String) -> Value {
+    let mut package = json!({
+        "SPDXID": spdx_id(drv_path),
+        "name": name,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "comment": comment,
+    });
+    if let Some(version) = version {
+        package["versionInfo"] = json!(version);
+    }
+    package
+}
+
+/// An SPDX 2.3 document of the root, the dependencies found to be in use.
+pub fn spdx(report: &RootReport, run: &RunInfo) -> Value {
+    let root_id = spdx_id(&report.root);
+    let mut packages = vec![spdx_package(
+        &report.root,
+        root_name(report),
+        &report.version,
+        format!("nix derivation {}", report.root),
+    )];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": root_id,
+    })];
+    for dep in &report.used {
+        packages.push(spdx_package(
+            &dep.drv_path,
+            dep_name(dep),
+            &dep.version,
+            match dep.attrs.first() {
+                Some(attr) => format!(
+                    "nix derivation {} ({}), used as {}",
+                    dep.drv_path,
+                    attr,
+                    dep.scanner.usage()
+                ),
+                None => format!(
+                    "nix derivation {}, used as {}",
+                    dep.drv_path,
+                    dep.scanner.usage()
+                ),
+            },
+        ));
+        let (kind, reversed) = spdx_relationship(dep.scanner);
+        let (from, to) = if reversed {
+            (spdx_id(&dep.drv_path), root_id.clone())
+        } else {
+            (root_id.clone(), spdx_id(&dep.drv_path))
+        };
+        relationships.push(json!({
+            "spdxElementId": from,
+            "relationshipType": kind,
+            "relatedSpdxElement": to,
+            "comment": dep.scanner.usage(),
+        }));
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": root_name(report),
+        // unique per root, as the drv path includes its hash
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}/{}",
+            env!("CARGO_PKG_NAME"),
+            store_basename(&report.root)
+        ),
+        "creationInfo": {
+            "created": format_rfc3339_seconds(run.started_at).to_string(),
+            "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
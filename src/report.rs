@@ -0,0 +1,535 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::derivation::drv_name;
+
+/// Result of scanning a single root derivation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RootReport {
+    pub root: String,
+    /// attribute the root was evaluated from, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attr: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// where the root is defined, if it was evaluated from an attribute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+    pub unused: Vec<UnusedDep>,
+    /// dependencies found to be in use and the scanner that found them
+    #[serde(default)]
+    pub used: Vec<UsedDep>,
+    #[serde(default)]
+    pub scanners: Vec<ScannerRun>,
+    /// inputs that were not checked, and why
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filtered: Vec<FilteredDep>,
+    /// problems that made the scan of this root incomplete
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureKind {
+    Evaluation,
+    Build,
+    SourceExtraction,
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FailureKind::Evaluation => "evaluation",
+            FailureKind::Build => "build",
+            FailureKind::SourceExtraction => "source-extraction",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scanner {
+    Headers,
+    Pyproject,
+    Shebangs,
+    SharedObjects,
+    /// store hashes of the dependency in the built outputs
+    References,
+}
+
+impl std::fmt::Display for Scanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Scanner::Headers => "headers",
+            Scanner::Pyproject => "pyproject",
+            Scanner::Shebangs => "shebangs",
+            Scanner::SharedObjects => "shared-objects",
+            Scanner::References => "references",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Scanner {
+    pub const ALL: [Scanner; 5] = [
+        Scanner::Headers,
+        Scanner::Pyproject,
+        Scanner::Shebangs,
+        Scanner::SharedObjects,
+        Scanner::References,
+    ];
+
+    /// How a dependency found by this scanner is used by the root.
+    pub fn usage(&self) -> &'static str {
+        match self {
+            Scanner::Headers => "included header",
+            Scanner::Pyproject => "python dependency",
+            Scanner::Shebangs => "interpreter",
+            Scanner::SharedObjects => "linked library",
+            Scanner::References => "referenced path",
+        }
+    }
+
+    /// Whether the scanner reads the unpacked source of the root rather than its outputs.
+    pub fn reads_source(&self) -> bool {
+        matches!(
+            self,
+            Scanner::Headers | Scanner::Pyproject | Scanner::Shebangs
+        )
+    }
+
+    /// Whether a dependency found by this scanner may only be needed to build the root, unlike e.g. a shebang interpreter.
+    pub fn build_only(&self) -> bool {
+        matches!(self, Scanner::Headers | Scanner::Pyproject)
+    }
+
+    /// What it means that this scanner did not find a dependency to be in use.
+    pub fn absence(&self) -> &'static str {
+        match self {
+            Scanner::Headers => "no header the dependency provides is included by the source",
+            Scanner::Pyproject => "the dependency is not listed in pyproject.toml",
+            Scanner::Shebangs => "no shebang uses a program the dependency provides",
+            Scanner::SharedObjects => "no ELF file links a library the dependency provides",
+            Scanner::References => "the outputs contain no reference to the dependency",
+        }
+    }
+}
+
+/// Kind of a finding, each kind is a separate rule in SARIF output.
+/// Dependencies in the wrong input list (e.g. a build tool in `buildInputs`) or only used by tests are not detected,
+/// only whether a build input is used at all.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FindingClass {
+    /// no scanner found the dependency in use
+    Unused,
+    /// no scanner found the dependency in use, but it is known to be used in ways they can not see
+    KnownRuntime,
+}
+
+impl FindingClass {
+    pub const ALL: [FindingClass; 2] = [FindingClass::Unused, FindingClass::KnownRuntime];
+
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            FindingClass::Unused => "unused-dependency",
+            FindingClass::KnownRuntime => "known-runtime-dependency",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FindingClass::Unused => "Dependency is not used by the package",
+            FindingClass::KnownRuntime => {
+                "Dependency is not used visibly, but is known to be used at runtime"
+            }
+        }
+    }
+}
+
+/// How likely a finding is to be correct.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Confidence {
+    /// known to be used in ways the scanners can not see
+    Low,
+    /// some scanners were disabled or could not read the source
+    Medium,
+    /// every scanner ran
+    High,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A location in a nix file.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub file: String,
+    /// 1-based
+    pub line: u32,
+    /// 1-based, unknown for `meta.position`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+}
+
+impl SourcePosition {
+    /// Parses `meta.position`, i.e. `file:line`.
+    pub fn from_meta_position(position: &str) -> Option<Self> {
+        let (file, line) = position.rsplit_once(':')?;
+        Some(SourcePosition {
+            file: file.to_owned(),
+            line: line.parse().ok()?,
+            column: None,
+        })
+    }
+
+    /// The file relative to the root of its source tree if it was copied to the store (e.g. a flake),
+    /// `None` if it is not in the store.
+    pub fn source_relative_file(&self) -> Option<&str> {
+        let in_store = self.file.strip_prefix("/nix/store/")?;
+        in_store.split_once('/').map(|(_, relative)| relative)
+    }
+
+    /// The store path of the source tree the file was copied to the store with, with a trailing `/`.
+    pub fn source_root(&self) -> Option<&str> {
+        let relative = self.source_relative_file()?;
+        Some(&self.file[..self.file.len() - relative.len()])
+    }
+
+    /// The file as CI systems and editors expect it: relative to its source tree or the working directory if possible.
+    pub fn workspace_file(&self) -> String {
+        if let Some(relative) = self.source_relative_file() {
+            return relative.to_owned();
+        }
+        std::env::current_dir()
+            .ok()
+            .and_then(|cwd| {
+                let relative = std::path::Path::new(&self.file).strip_prefix(cwd).ok()?;
+                Some(relative.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| self.file.clone())
+    }
+}
+
+impl std::fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which input attribute of the root a dependency was declared in.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum InputCategory {
+    /// only `buildInputs` are checked so far
+    #[default]
+    BuildInputs,
+}
+
+/// Statistics of one scanner run on one root.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ScannerRun {
+    pub scanner: Scanner,
+    /// dependencies still considered unused when the scanner started
+    pub checked: usize,
+    /// dependencies the scanner found to be in use
+    pub hits: usize,
+    pub seconds: f64,
+}
+
+/// Why an input of a root is not checked.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterReason {
+    /// not in `buildInputs`, e.g. `nativeBuildInputs` or the source
+    NotBuildInput,
+    /// propagated by another input
+    Propagated,
+    /// also in `checkInputs`
+    CheckInput,
+    /// allowed to be unused by the configuration
+    Allowlisted,
+}
+
+impl std::fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FilterReason::NotBuildInput => "not-build-input",
+            FilterReason::Propagated => "propagated",
+            FilterReason::CheckInput => "check-input",
+            FilterReason::Allowlisted => "allowlisted",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FilteredDep {
+    pub drv_path: String,
+    pub reason: FilterReason,
+    /// reason given in the configuration for allowlisted dependencies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl FilteredDep {
+    pub fn new(drv_path: &str, reason: FilterReason) -> Self {
+        FilteredDep {
+            drv_path: drv_path.to_owned(),
+            reason,
+            detail: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsedDep {
+    pub drv_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// attribute paths of the dependency, see `--attr-index`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attrs: Vec<String>,
+    /// the first scanner that found the dependency in use, later ones don't check it anymore
+    pub scanner: Scanner,
+}
+
+/// A dependency of a root that was not found to be in use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnusedDep {
+    pub drv_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pname: Option<String>,
+    /// attribute paths of the dependency, see `--attr-index`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attrs: Vec<String>,
+    /// output name to store path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+    #[serde(default)]
+    pub input_category: InputCategory,
+    /// where the dependency is declared in the nix file of the root, if found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+    /// bytes that disappear from the closure if this dependency is removed, if computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closure_bytes: Option<u64>,
+    /// known vulnerabilities that leave the closure of the root if the dependency is removed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub advisories: Vec<Advisory>,
+    /// set if the dependency is known to be used in ways the scanners can not see
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub known_runtime: Option<KnownRuntime>,
+}
+
+/// A known vulnerability of a package, read from `--advisories`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Advisory {
+    /// e.g. `CVE-2024-1234`
+    pub id: String,
+    /// `name-version` of the affected package
+    pub package: String,
+    /// CVSSv3 base score, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// an advisory of the dependency itself whose closure could not be measured, it may stay in the closure of the root
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unverified: bool,
+}
+
+impl std::fmt::Display for Advisory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.id)?;
+        if self.unverified {
+            f.write_str(" (unverified)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a dependency might be used despite no scanner finding it, and how to check.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct KnownRuntime {
+    pub reason: String,
+    pub verify: String,
+}
+
+impl std::fmt::Display for KnownRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "known runtime-only: verify manually, {}; {}",
+            self.reason, self.verify
+        )
+    }
+}
+
+impl UnusedDep {
+    /// Shortest attribute path if known, pname or drv name otherwise.
+    pub fn name(&self) -> String {
+        self.attrs
+            .first()
+            .cloned()
+            .or_else(|| self.pname.clone())
+            .unwrap_or_else(|| drv_name(&self.drv_path))
+    }
+
+    /// Advisories eliminated and bytes saved by removing the dependency, for ranking.
+    pub fn savings(&self) -> (usize, u64) {
+        (self.advisories.len(), self.closure_bytes.unwrap_or(0))
+    }
+
+    pub fn class(&self) -> FindingClass {
+        if self.known_runtime.is_some() {
+            FindingClass::KnownRuntime
+        } else {
+            FindingClass::Unused
+        }
+    }
+}
+
+impl RootReport {
+    pub fn source_unreadable(&self) -> bool {
+        self.failures
+            .iter()
+            .any(|f| f.kind == FailureKind::SourceExtraction)
+    }
+
+    /// Scanners that checked the root, the source scanners only if the source could be unpacked.
+    pub fn effective_scanners(&self) -> impl Iterator<Item = &ScannerRun> {
+        let source_unreadable = self.source_unreadable();
+        self.scanners
+            .iter()
+            .filter(move |r| !(source_unreadable && r.scanner.reads_source()))
+    }
+
+    pub fn confidence(&self, dep: &UnusedDep) -> Confidence {
+        if dep.known_runtime.is_some() {
+            Confidence::Low
+        } else if self.effective_scanners().count() < Scanner::ALL.len() {
+            Confidence::Medium
+        } else {
+            Confidence::High
+        }
+    }
+
+    /// Where a finding about `dep` points to: its declaration if found, the root otherwise.
+    pub fn position_of<'a>(&'a self, dep: &'a UnusedDep) -> Option<&'a SourcePosition> {
+        dep.position.as_ref().or(self.position.as_ref())
+    }
+
+    /// Attribute path if known, pname or drv name otherwise.
+    pub fn name(&self) -> String {
+        self.attr
+            .clone()
+            .or_else(|| self.pname.clone())
+            .unwrap_or_else(|| drv_name(&self.root))
+    }
+
+    pub fn display_name(&self) -> String {
+        match &self.attr {
+            Some(attr) => format!("{} ({})", self.root, attr),
+            None => self.root.clone(),
+        }
+    }
+}
+
+/// Orders findings by the advisories their removal eliminates, then by the closure size it saves, biggest first.
+/// Roots are ordered by the totals of all their unused dependencies.
+pub fn rank_by_savings(reports: &mut [RootReport]) {
+    for report in reports.iter_mut() {
+        report
+            .unused
+            .sort_by_key(|d| std::cmp::Reverse(d.savings()));
+    }
+    reports.sort_by_key(|r| {
+        std::cmp::Reverse(
+            r.unused
+                .iter()
+                .map(UnusedDep::savings)
+                .fold((0, 0), |(advisories, bytes), (a, b)| {
+                    (advisories + a, bytes + b)
+                }),
+        )
+    });
+}
+
+/// Comma separated ids of advisories, e.g. for `(removes CVE-2024-1, CVE-2024-2)`.
+pub fn advisory_ids(advisories: &[Advisory]) -> String {
+    advisories
+        .iter()
+        .map(Advisory::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_source_is_no_evidence() {
+        let mut report: RootReport = serde_json::from_value(serde_json::json!({
+            "root": "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-foo-1.drv",
+            "unused": [ { "drv_path": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-zlib-1.3.drv" } ],
+            "scanners": Scanner::ALL.map(|s| ScannerRun { scanner: s, checked: 1, hits: 0, seconds: 0.0 }),
+        }))
+        .unwrap();
+        assert_eq!(report.confidence(&report.unused[0]), Confidence::High);
+
+        report.failures.push(Failure {
+            kind: FailureKind::SourceExtraction,
+            message: "no source".to_owned(),
+        });
+        let effective: Vec<Scanner> = report.effective_scanners().map(|r| r.scanner).collect();
+        assert_eq!(effective, [Scanner::SharedObjects, Scanner::References]);
+        assert_eq!(report.confidence(&report.unused[0]), Confidence::Medium);
+    }
+}
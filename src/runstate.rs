@@ -0,0 +1,193 @@
+use log::warn;
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::report::RootReport;
+
+/// Deterministic part `index` (1-based) out of `count` parts of all scan roots.
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected <index>/<count>, got {}", s))?;
+        let index: u64 = index
+            .parse()
+            .map_err(|e| format!("bad shard index: {}", e))?;
+        let count: u64 = count
+            .parse()
+            .map_err(|e| format!("bad shard count: {}", e))?;
+        if index == 0 || index > count {
+            return Err(format!("shard index must be between 1 and {}", count));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Shard {
+    /// Whether a root belongs to this shard. Only depends on the drv path, so every machine splits the same way.
+    pub fn contains(&self, drv_path: &str) -> bool {
+        fnv1a(drv_path.as_bytes()) % self.count == self.index - 1
+    }
+}
+
+// std's hashers are not guaranteed to be stable across releases, so roll a tiny one
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Append-only JSON lines file of every completed scan root, used to resume interrupted runs.
+pub struct RunState {
+    file: File,
+    completed: HashMap<String, RootReport>,
+}
+
+impl RunState {
+    pub fn open(path: &Path, resume: bool) -> io::Result<Self> {
+        if path.exists() && !resume {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "state file {} already exists, pass --resume to continue the run",
+                    path.display()
+                ),
+            ));
+        }
+        let completed = if resume && path.exists() {
+            read_state_file(path)?
+                .into_iter()
+                .map(|r| (r.root.clone(), r))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        // a run that crashed mid-write leaves a truncated last line, which must not swallow the next record
+        if file.seek(SeekFrom::End(0))? > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            let mut last = [0];
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(RunState { file, completed })
+    }
+
+    pub fn is_completed(&self, drv_path: &str) -> bool {
+        self.completed.contains_key(drv_path)
+    }
+
+    pub fn completed(&self) -> impl Iterator<Item = &RootReport> {
+        self.completed.values()
+    }
+
+    pub fn record(&mut self, report: &RootReport) -> io::Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(report)?)?;
+        self.file.flush()
+    }
+}
+
+/// Reads all root reports of a state file. Lines that can not be parsed, e.g. because a run crashed mid-write, are skipped.
+pub fn read_state_file(path: &Path) -> io::Result<Vec<RootReport>> {
+    let mut reports = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(report) => reports.push(report),
+            Err(e) => warn!("skipping bad line in {}: {}", path.display(), e),
+        }
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nix-check-deps-{}-{}.jsonl",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn report(root: &str) -> RootReport {
+        serde_json::from_value(serde_json::json!({ "root": root, "unused": [] })).unwrap()
+    }
+
+    #[test]
+    fn parse_shards() {
+        let shard: Shard = "2/3".parse().unwrap();
+        assert_eq!((shard.index, shard.count), (2, 3));
+        for bad in ["3", "0/3", "4/3", "x/3", "1/y", "1/0"] {
+            assert!(bad.parse::<Shard>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn shards_split_evenly() {
+        let shards: Vec<Shard> = (1..=4).map(|index| Shard { index, count: 4 }).collect();
+        let mut sizes = [0; 4];
+        for i in 0..4000 {
+            let drv_path = format!("/nix/store/{:032x}-package-{}.drv", i * 7919, i);
+            let containing: Vec<usize> =
+                (0..4).filter(|s| shards[*s].contains(&drv_path)).collect();
+            assert_eq!(containing.len(), 1, "{}", drv_path);
+            sizes[containing[0]] += 1;
+        }
+        assert!(sizes.iter().all(|s| (800..1200).contains(s)), "{:?}", sizes);
+    }
+
+    #[test]
+    fn resume_after_truncated_line() {
+        let path = temp_file("truncated");
+        let complete = serde_json::to_string(&report("/nix/store/a-foo.drv")).unwrap();
+        fs::write(&path, format!("{}\n{{\"root\":\"/nix/st", complete)).unwrap();
+        assert_eq!(read_state_file(&path).unwrap().len(), 1);
+
+        let mut state = RunState::open(&path, true).unwrap();
+        assert!(state.is_completed("/nix/store/a-foo.drv"));
+        state.record(&report("/nix/store/b-bar.drv")).unwrap();
+        let roots: Vec<String> = read_state_file(&path)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.root)
+            .collect();
+        assert_eq!(roots, ["/nix/store/a-foo.drv", "/nix/store/b-bar.drv"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn existing_state_needs_resume() {
+        let path = temp_file("exists");
+        fs::write(&path, "").unwrap();
+        assert!(RunState::open(&path, false).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use grep::{
+    regex::RegexMatcher,
+    searcher::{sinks::Bytes, BinaryDetection, Searcher},
+};
+use ignore::Walk;
+use log::{error, info, warn};
+use std::{fs, time::Instant};
+
+use crate::{
+    derivation::{self, Derivation, UnreadableSrc},
+    report::{Failure, FailureKind, Scanner, ScannerRun, UsedDep},
+};
+
+/// Which scanners run on every root.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub check_headers: bool,
+    pub list_used_headers: bool,
+    pub skip_dep_usage_check: bool,
+    pub check_pyproject: bool,
+    pub check_shebangs: bool,
+    pub check_shared_objects: bool,
+}
+
+/// Result of running all scanners on one root.
+pub struct ScanOutcome {
+    /// dependencies none of the scanners found to be in use
+    pub unused: Vec<Derivation>,
+    pub used: Vec<UsedDep>,
+    pub scanners: Vec<ScannerRun>,
+    pub failures: Vec<Failure>,
+}
+
+/// Bookkeeping of which scanner marked which dependency as used.
+struct Scan {
+    remaining: Vec<Derivation>,
+    used: Vec<UsedDep>,
+    scanners: Vec<ScannerRun>,
+}
+
+impl Scan {
+    /// Runs a scanner that removes every dependency it finds to be in use from the remaining ones.
+    fn run(&mut self, scanner: Scanner, retain_unused: impl FnOnce(&mut Vec<Derivation>)) {
+        let start = Instant::now();
+        let before: Vec<UsedDep> = self
+            .remaining
+            .iter()
+            .map(|d| UsedDep {
+                drv_path: d.drv_path.clone(),
+                pname: d.pname().map(str::to_owned),
+                version: d.version().map(str::to_owned),
+                attrs: Vec::new(),
+                scanner,
+            })
+            .collect();
+        retain_unused(&mut self.remaining);
+        let used: Vec<UsedDep> = before
+            .into_iter()
+            .filter(|u| !self.remaining.iter().any(|d| d.drv_path == u.drv_path))
+            .collect();
+        let elapsed = start.elapsed();
+        info!("check-{} took {:.2?} seconds", scanner, elapsed);
+        self.scanners.push(ScannerRun {
+            scanner,
+            checked: self.remaining.len() + used.len(),
+            hits: used.len(),
+            seconds: elapsed.as_secs_f64(),
+        });
+        self.used.extend(used);
+    }
+}
+
+/// Runs all enabled scanners on `root`, returns `None` if dependency usage is not checked at all.
+pub fn scan_root(
+    root: &Derivation,
+    deps: Vec<Derivation>,
+    options: &ScanOptions,
+) -> Option<ScanOutcome> {
+    let mut scan = Scan {
+        remaining: deps,
+        used: Vec::new(),
+        scanners: Vec::new(),
+    };
+    let mut failures = Vec::new();
+
+    let uses_src = options.check_headers
+        || options.list_used_headers
+        || options.check_pyproject
+        || options.check_shebangs;
+    // an unsupported format is a limit of the scanners, not a failure: they are skipped and the findings less certain
+    let mut skip_src = false;
+    match root.unreadable_src().filter(|_| uses_src) {
+        Some(UnreadableSrc::Broken) => failures.push(Failure {
+            kind: FailureKind::SourceExtraction,
+            message: format!("source of {} could not be unpacked", root.drv_path),
+        }),
+        Some(UnreadableSrc::Unsupported) => {
+            warn!(
+                "source of {} is an archive the source scanners can't unpack, skipping them",
+                root.drv_path
+            );
+            skip_src = true;
+        }
+        None => {}
+    }
+
+    if (options.check_headers || options.list_used_headers) && !skip_src {
+        scan.run(Scanner::Headers, |dep_relations| {
+            let used_headers = root.find_used_c_headers();
+            dep_relations.retain(|dep_drv| {
+                !dep_drv
+                    .get_provided_c_headers()
+                    .intersection(&used_headers)
+                    .any(|_| true)
+            });
+            if options.list_used_headers {
+                for header in used_headers {
+                    info!("{} uses header: {}", root.drv_path, header);
+                }
+            }
+        });
+    }
+
+    if options.skip_dep_usage_check {
+        return None;
+    }
+
+    if options.check_pyproject && !skip_src {
+        scan.run(Scanner::Pyproject, |dep_relations| {
+            let used_py_deps = root.find_used_pyproject_deps();
+            dep_relations
+                .retain(|dep_drv| !used_py_deps.iter().any(|py| dep_drv.matches_pname(py)));
+        });
+    }
+
+    if options.check_shebangs && !skip_src {
+        scan.run(Scanner::Shebangs, |dep_relations| {
+            let used_shebangs = root.find_used_shebangs();
+            dep_relations.retain(|dep_drv| {
+                !dep_drv
+                    .get_provided_binaries()
+                    .intersection(&used_shebangs)
+                    .any(|_| true)
+            });
+        });
+    }
+
+    if options.check_shared_objects {
+        scan.run(Scanner::SharedObjects, |dep_relations| {
+            let used_shared_objects = root.find_used_shared_objects();
+            dep_relations.retain(|dep_drv| {
+                !dep_drv
+                    .find_provided_shared_objects()
+                    .intersection(&used_shared_objects)
+                    .any(|_| true)
+            });
+        });
+    }
+
+    // make sure the package exists in local store so it can be scanned
+    let pkg_outputs = match root.build() {
+        Ok(pkg_outputs) => pkg_outputs,
+        Err(e) => {
+            error!(
+                "derivation {} does not build, skipping checks...",
+                root.drv_path
+            );
+            failures.push(Failure {
+                kind: FailureKind::Build,
+                message: e.to_string(),
+            });
+            return Some(ScanOutcome {
+                unused: Vec::new(),
+                used: scan.used,
+                scanners: scan.scanners,
+                failures,
+            });
+        }
+    };
+
+    scan.run(Scanner::References, |dep_relations| {
+        let mut searcher = Searcher::new();
+        searcher.set_binary_detection(BinaryDetection::none());
+        for output in pkg_outputs {
+            for e in Walk::new(output).flat_map(Result::into_iter) {
+                let is_file = e.file_type().is_some_and(|f| f.is_file());
+                let is_link = e.file_type().is_some_and(|f| f.is_symlink());
+
+                if is_file {
+                    dep_relations.retain(|dep_drv| {
+                        let mut found = false;
+                        let regex: String = dep_drv
+                            .get_out_paths()
+                            .iter()
+                            .map(|dep| derivation::get_store_hash(dep))
+                            .collect::<Vec<String>>()
+                            .join("|");
+                        let matcher = RegexMatcher::new(&regex).unwrap();
+                        searcher
+                            .search_path(
+                                &matcher,
+                                e.path(),
+                                Bytes(|_, _| {
+                                    found = true;
+                                    Ok(false) // stop reading the file
+                                }),
+                            )
+                            .ok();
+                        !found
+                    });
+                } else if is_link {
+                    dep_relations.retain(|dep_drv| {
+                        let p = fs::read_link(e.path()).unwrap();
+                        for dep in dep_drv.get_out_paths() {
+                            if p.to_string_lossy()
+                                .contains(&derivation::get_store_hash(&dep))
+                            {
+                                return false;
+                            }
+                        }
+                        true
+                    });
+                }
+            }
+        }
+    });
+
+    Some(ScanOutcome {
+        unused: scan.remaining,
+        used: scan.used,
+        scanners: scan.scanners,
+        failures,
+    })
+}
@@ -0,0 +1,47 @@
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::derivation::Derivation;
+
+/// Which closure `--tree` walks to find further roots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ClosureKind {
+    /// every derivation needed to build the package
+    #[default]
+    Build,
+    /// only derivations of store paths the built package references at runtime
+    Runtime,
+}
+
+/// Selection of the transitive roots scanned in `--tree` mode.
+#[derive(Debug, Default)]
+pub struct Scope {
+    pub closure: ClosureKind,
+    pub max_depth: Option<usize>,
+    pub only_with_build_inputs: bool,
+    pub exclude_fixed_output: bool,
+    pub pname: Option<Regex>,
+}
+
+impl Scope {
+    pub fn requisites(&self, drv: &Derivation) -> Vec<Derivation> {
+        let requisites = match (self.closure, self.max_depth) {
+            (ClosureKind::Runtime, _) => drv.get_runtime_requisites(),
+            (ClosureKind::Build, Some(depth)) => drv.get_requisites_to_depth(depth),
+            (ClosureKind::Build, None) => drv.get_requisites(),
+        };
+        requisites.into_iter().filter(|d| self.accepts(d)).collect()
+    }
+
+    fn accepts(&self, drv: &Derivation) -> bool {
+        if self.only_with_build_inputs && !drv.has_build_inputs() {
+            return false;
+        }
+        if self.exclude_fixed_output && drv.is_fixed_output() {
+            return false;
+        }
+        self.pname
+            .as_ref()
+            .is_none_or(|re| drv.pname().is_some_and(|p| re.is_match(p)))
+    }
+}
@@ -0,0 +1,182 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    derivation::drv_name,
+    report::{RootReport, Scanner},
+};
+
+/// How many entries the "most unused" lists show.
+const TOP_ENTRIES: usize = 20;
+
+// package sets prefix their drv names with the interpreter they are built for, e.g. `python3.12-requests-2.32.3`
+static ECOSYSTEMS: Lazy<Vec<(&str, Regex)>> = Lazy::new(|| {
+    vec![
+        ("python", Regex::new(r"^python[0-9.]*-").unwrap()),
+        ("perl", Regex::new(r"^perl[0-9.]*-").unwrap()),
+        ("lua", Regex::new(r"^(lua|luajit)[0-9.]*-").unwrap()),
+        ("ocaml", Regex::new(r"^ocaml[0-9.]*-").unwrap()),
+        ("emacs", Regex::new(r"^emacs-").unwrap()),
+        ("php", Regex::new(r"^php[0-9.]*-").unwrap()),
+        ("r", Regex::new(r"^r-").unwrap()),
+    ]
+});
+
+pub fn ecosystem(drv_path: &str) -> &'static str {
+    let name = drv_name(drv_path);
+    ECOSYSTEMS
+        .iter()
+        .find(|(_, re)| re.is_match(&name))
+        .map_or("other", |(eco, _)| eco)
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ScannerStats {
+    /// roots the scanner ran on
+    pub runs: usize,
+    /// dependencies still considered unused when the scanner ran
+    pub checked: usize,
+    /// dependencies the scanner found to be in use
+    pub hits: usize,
+    pub hit_rate: f64,
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct EcosystemStats {
+    pub roots: usize,
+    pub roots_with_unused: usize,
+    pub unused: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+/// Summary over the findings of many roots, to spot systemic issues.
+#[derive(Serialize, Debug, Default)]
+pub struct Stats {
+    pub roots: usize,
+    pub roots_with_unused: usize,
+    pub unused: usize,
+    /// dependencies unused by the most roots
+    pub most_unused_deps: Vec<Count>,
+    /// roots with the most unused dependencies
+    pub roots_with_most_unused: Vec<Count>,
+    pub scanners: BTreeMap<Scanner, ScannerStats>,
+    pub ecosystems: BTreeMap<String, EcosystemStats>,
+}
+
+impl Stats {
+    pub fn from_reports(reports: &[RootReport]) -> Self {
+        let mut stats = Stats {
+            roots: reports.len(),
+            ..Default::default()
+        };
+        let mut unused_deps: HashMap<String, usize> = HashMap::new();
+        let mut roots_with_most_unused: Vec<Count> = Vec::new();
+
+        for report in reports {
+            stats.unused += report.unused.len();
+            if !report.unused.is_empty() {
+                stats.roots_with_unused += 1;
+                roots_with_most_unused.push(Count {
+                    name: report.name(),
+                    count: report.unused.len(),
+                });
+            }
+            for dep in &report.unused {
+                *unused_deps.entry(drv_name(&dep.drv_path)).or_default() += 1;
+            }
+
+            for run in &report.scanners {
+                let scanner = stats.scanners.entry(run.scanner).or_default();
+                scanner.runs += 1;
+                scanner.checked += run.checked;
+                scanner.hits += run.hits;
+                scanner.seconds += run.seconds;
+            }
+
+            let eco = stats
+                .ecosystems
+                .entry(ecosystem(&report.root).to_owned())
+                .or_default();
+            eco.roots += 1;
+            eco.unused += report.unused.len();
+            if !report.unused.is_empty() {
+                eco.roots_with_unused += 1;
+            }
+        }
+
+        for scanner in stats.scanners.values_mut() {
+            if scanner.checked > 0 {
+                scanner.hit_rate = scanner.hits as f64 / scanner.checked as f64;
+            }
+        }
+
+        let mut most_unused_deps: Vec<Count> = unused_deps
+            .into_iter()
+            .map(|(name, count)| Count { name, count })
+            .collect();
+        most_unused_deps.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+        most_unused_deps.truncate(TOP_ENTRIES);
+        stats.most_unused_deps = most_unused_deps;
+
+        roots_with_most_unused.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+        roots_with_most_unused.truncate(TOP_ENTRIES);
+        stats.roots_with_most_unused = roots_with_most_unused;
+
+        stats
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} roots scanned, {} with unused dependencies, {} unused dependencies in total",
+            self.roots, self.roots_with_unused, self.unused
+        )?;
+
+        writeln!(f, "\nmost frequently unused dependencies:")?;
+        for c in &self.most_unused_deps {
+            writeln!(f, "  {:>5}  {}", c.count, c.name)?;
+        }
+
+        writeln!(f, "\nroots with the most unused dependencies:")?;
+        for c in &self.roots_with_most_unused {
+            writeln!(f, "  {:>5}  {}", c.count, c.name)?;
+        }
+
+        writeln!(f, "\nscanners:")?;
+        for (scanner, s) in &self.scanners {
+            writeln!(
+                f,
+                "  {:<15} {:>5} runs, {:>6} of {:>6} dependencies found in use ({:.1}%), {:.1}s",
+                scanner.to_string(),
+                s.runs,
+                s.hits,
+                s.checked,
+                s.hit_rate * 100.0,
+                s.seconds
+            )?;
+        }
+
+        writeln!(f, "\necosystems:")?;
+        for (eco, s) in &self.ecosystems {
+            writeln!(
+                f,
+                "  {:<10} {:>5} roots, {:>5} with unused dependencies, {:>5} unused dependencies",
+                eco, s.roots, s.roots_with_unused, s.unused
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use log::{error, warn};
+use regex::Regex;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+use crate::derivation;
+
+/// A single package to scan, given either as installable/path or as already evaluated `.drv` path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Target {
+    pub attr: Option<String>,
+    pub drv_path: Option<String>,
+    /// output paths already known for `drv_path`, e.g. from `nix-eval-jobs`
+    pub outputs: Vec<String>,
+    /// `meta.position` of the package, `file:line`
+    pub position: Option<String>,
+}
+
+/// A JSON target line, this is a subset of what `nix-eval-jobs` emits.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetObject {
+    #[serde(default)]
+    attr: Option<String>,
+    #[serde(default)]
+    drv_path: Option<String>,
+    #[serde(default)]
+    outputs: HashMap<String, Option<String>>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TargetLine {
+    Plain(String),
+    Object(TargetObject),
+}
+
+/// Restricts which packages of a recursed attribute set are scanned.
+#[derive(Debug, Default)]
+pub struct AttrFilter {
+    pub include: Option<Regex>,
+    pub exclude: Option<Regex>,
+}
+
+impl AttrFilter {
+    fn matches(&self, attr: &str) -> bool {
+        self.include.as_ref().is_none_or(|re| re.is_match(attr))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(attr))
+    }
+}
+
+impl Target {
+    /// Finds the `.drv` paths of the target, evaluating it if needed.
+    /// Attribute sets expand to one target per contained package, `None` means the target could not be resolved.
+    pub fn resolve(&self, filter: &AttrFilter) -> Option<Vec<Target>> {
+        if self.drv_path.is_some() {
+            return Some(vec![self.clone()]);
+        }
+        let attr = self.attr.as_ref()?;
+
+        if attr.ends_with(".drv") && Path::new(attr).exists() {
+            return Some(vec![Target {
+                attr: None,
+                drv_path: Some(attr.clone()),
+                ..Default::default()
+            }]);
+        }
+        if derivation::to_store_path(attr).is_some() {
+            return resolve_store_path(attr).map(|drv_path| {
+                vec![Target {
+                    attr: None,
+                    drv_path: Some(drv_path),
+                    ..Default::default()
+                }]
+            });
+        }
+
+        let Some(evaluated) = derivation::eval_attr_to_drv_paths(attr) else {
+            error!("can not evaluate {} to a derivation", attr);
+            return None;
+        };
+        Some(
+            evaluated
+                .into_iter()
+                .filter(|e| e.attr.is_empty() || filter.matches(&e.attr))
+                .map(|e| Target {
+                    attr: Some(join_attr_path(attr, &e.attr)),
+                    drv_path: Some(e.drv_path),
+                    outputs: e.outputs.into_values().collect(),
+                    position: e.position,
+                })
+                .collect(),
+        )
+    }
+}
+
+fn join_attr_path(installable: &str, attr: &str) -> String {
+    if attr.is_empty() {
+        installable.to_owned()
+    } else if installable.contains('#') {
+        format!("{}.{}", installable, attr)
+    } else {
+        format!("{}#{}", installable, attr)
+    }
+}
+
+/// Parses a newline separated list of targets.
+///
+/// Every line is either a plain installable/path, a JSON string or a JSON object with `attr` and/or `drvPath`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_targets(input: &str) -> Vec<Target> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .flat_map(|line| {
+            if !line.starts_with('{') && !line.starts_with('"') {
+                return Some(Target {
+                    attr: Some(line.to_owned()),
+                    drv_path: None,
+                    ..Default::default()
+                });
+            }
+            match serde_json::from_str::<TargetLine>(line) {
+                Ok(TargetLine::Plain(attr)) => Some(Target {
+                    attr: Some(attr),
+                    drv_path: None,
+                    ..Default::default()
+                }),
+                Ok(TargetLine::Object(TargetObject {
+                    attr,
+                    error: Some(e),
+                    ..
+                })) => {
+                    warn!(
+                        "skipping {} which failed to evaluate: {}",
+                        attr.unwrap_or_default(),
+                        e
+                    );
+                    None
+                }
+                Ok(TargetLine::Object(obj)) if obj.attr.is_some() || obj.drv_path.is_some() => {
+                    Some(Target {
+                        attr: obj.attr,
+                        drv_path: obj.drv_path,
+                        outputs: obj.outputs.into_values().flatten().collect(),
+                        ..Default::default()
+                    })
+                }
+                Ok(TargetLine::Object(_)) => {
+                    warn!("target without attr or drvPath: {}", line);
+                    None
+                }
+                Err(e) => {
+                    warn!("can not parse target {}: {}", line, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Maps an output store path (e.g. `./result`) to the `.drv` path of its deriver.
+fn resolve_store_path(path: &str) -> Option<String> {
+    let store_path = derivation::to_store_path(path)?;
+    let Some(deriver) = derivation::query_deriver(&store_path) else {
+        error!("can not determine the deriver of {}", store_path);
+        return None;
+    };
+    if !Path::new(&deriver).exists() {
+        error!(
+            "deriver {} of {} is missing from the store, pass the attribute it was built from instead \
+             or fetch the deriver with `nix copy --derivation --from <substituter> {}`",
+            deriver, store_path, deriver
+        );
+        return None;
+    }
+    Some(deriver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(attr: &str) -> Target {
+        Target {
+            attr: Some(attr.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plain_lines_and_json_strings() {
+        let targets = parse_targets(
+            "nixpkgs#hello\n\n  # a comment\n\"nixpkgs#curl\"\n  ./result  \n#nixpkgs#skipped\n",
+        );
+        assert_eq!(
+            targets,
+            vec![
+                attr("nixpkgs#hello"),
+                attr("nixpkgs#curl"),
+                attr("./result")
+            ]
+        );
+    }
+
+    #[test]
+    fn json_objects() {
+        let targets = parse_targets(concat!(
+            r#"{"attr": "nixpkgs#hello"}"#,
+            "\n",
+            r#"{"drvPath": "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.drv"}"#,
+            "\n",
+            r#"{"system": "x86_64-linux"}"#,
+            "\n",
+            "{not json",
+        ));
+        assert_eq!(
+            targets,
+            vec![
+                attr("nixpkgs#hello"),
+                Target {
+                    drv_path: Some(
+                        "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.drv".to_owned()
+                    ),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nix_eval_jobs_lines() {
+        let targets = parse_targets(concat!(
+            r#"{"attr": "hello", "attrPath": ["hello"], "drvPath": "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.drv", "outputs": {"out": "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-hello-2.12", "dev": null}, "system": "x86_64-linux"}"#,
+            "\n",
+            r#"{"attr": "broken", "attrPath": ["broken"], "error": "error: Package is marked as broken"}"#,
+        ));
+        assert_eq!(
+            targets,
+            vec![Target {
+                attr: Some("hello".to_owned()),
+                drv_path: Some(
+                    "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12.drv".to_owned()
+                ),
+                outputs: vec!["/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-hello-2.12".to_owned()],
+                position: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn attr_paths() {
+        assert_eq!(join_attr_path("nixpkgs#xorg", ""), "nixpkgs#xorg");
+        assert_eq!(
+            join_attr_path("nixpkgs#xorg", "libX11"),
+            "nixpkgs#xorg.libX11"
+        );
+        assert_eq!(join_attr_path(".", "hello"), ".#hello");
+        assert_eq!(
+            join_attr_path("github:NixOS/nixpkgs#python3Packages", "requests"),
+            "github:NixOS/nixpkgs#python3Packages.requests"
+        );
+    }
+
+    #[test]
+    fn attr_filter() {
+        let filter = AttrFilter {
+            include: Some(Regex::new("^lib").unwrap()),
+            exclude: Some(Regex::new("Dev$").unwrap()),
+        };
+        assert!(filter.matches("libX11"));
+        assert!(!filter.matches("libXDev"));
+        assert!(!filter.matches("xclock"));
+        assert!(AttrFilter::default().matches("xclock"));
+    }
+}